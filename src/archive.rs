@@ -0,0 +1,127 @@
+use anyhow::{Result, anyhow};
+use rusqlite::{params, Connection};
+use std::fs;
+use std::path::Path;
+
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE downloads (
+        id INTEGER PRIMARY KEY,
+        target TEXT NOT NULL,
+        media_id TEXT,
+        url TEXT NOT NULL,
+        source TEXT NOT NULL,
+        captured_at INTEGER NOT NULL,
+        path TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        sha256 TEXT NOT NULL
+    );
+    CREATE INDEX idx_downloads_target ON downloads(target);
+    CREATE INDEX idx_downloads_media_id ON downloads(media_id);
+    CREATE INDEX idx_downloads_sha256 ON downloads(sha256);",
+    "ALTER TABLE downloads ADD COLUMN dedup_key TEXT;
+    UPDATE downloads SET dedup_key = COALESCE(media_id, 'sha256:' || sha256);
+    CREATE INDEX idx_downloads_dedup_key ON downloads(dedup_key);",
+    "ALTER TABLE downloads ADD COLUMN taken_at INTEGER;",
+    "ALTER TABLE downloads ADD COLUMN mime TEXT;",
+    "ALTER TABLE downloads ADD COLUMN width INTEGER;
+    ALTER TABLE downloads ADD COLUMN height INTEGER;
+    ALTER TABLE downloads ADD COLUMN duration REAL;",
+    "ALTER TABLE downloads ADD COLUMN overlays TEXT;",
+];
+
+pub fn dedup_key(media_id: Option<&str>, sha256: &str) -> String {
+    match media_id {
+        Some(id) => id.to_string(),
+        None => format!("sha256:{}", sha256),
+    }
+}
+
+pub struct ArchiveEntry<'a> {
+    pub target: &'a str,
+    pub media_id: Option<&'a str>,
+    pub url: &'a str,
+    pub source: &'a str,
+    pub taken_at: Option<i64>,
+    pub captured_at: i64,
+    pub path: &'a str,
+    pub size: u64,
+    pub sha256: &'a str,
+    pub mime: &'a str,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub duration: Option<f64>,
+    pub overlays: Option<&'a str>,
+    pub dedup_key: &'a str,
+}
+
+pub struct Archive {
+    conn: Connection,
+}
+
+impl Archive {
+    pub fn open(path: &str) -> Result<Self> {
+        if let Some(parent) = Path::new(path).parent() {
+            if !parent.as_os_str().is_empty() { fs::create_dir_all(parent)?; }
+        }
+        let mut conn = Connection::open(path).map_err(|e| anyhow!("Failed to open archive database {}: {}", path, e))?;
+        migrate(&mut conn).map_err(|e| anyhow!("Failed to migrate archive database {}: {}", path, e))?;
+        Ok(Self { conn })
+    }
+
+    pub fn record(&self, entry: &ArchiveEntry) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO downloads (target, media_id, url, source, taken_at, captured_at, path, size, sha256, mime, width, height, duration, overlays, dedup_key)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![
+                entry.target,
+                entry.media_id,
+                entry.url,
+                entry.source,
+                entry.taken_at,
+                entry.captured_at,
+                entry.path,
+                entry.size as i64,
+                entry.sha256,
+                entry.mime,
+                entry.width,
+                entry.height,
+                entry.duration,
+                entry.overlays,
+                entry.dedup_key,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn contains_key(&self, key: &str) -> Result<bool> {
+        let found = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM downloads WHERE dedup_key = ?1)",
+            params![key],
+            |row| row.get(0),
+        )?;
+        Ok(found)
+    }
+
+    pub fn contains_hash(&self, sha256: &str) -> Result<bool> {
+        let found = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM downloads WHERE sha256 = ?1)",
+            params![sha256],
+            |row| row.get(0),
+        )?;
+        Ok(found)
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let version = version as usize;
+    if version >= MIGRATIONS.len() { return Ok(()); }
+
+    let tx = conn.transaction()?;
+    for (idx, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", (idx + 1) as i64)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
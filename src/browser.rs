@@ -4,10 +4,11 @@ use std::path::PathBuf;
 use std::process::Command;
 use std::ffi::OsStr;
 use std::env;
-use crate::config::{USER_AGENT, CHROME_PATH};
+use tracing::info;
+use crate::config;
 
 fn find_chromium_path() -> Result<PathBuf> {
-    let p1 = PathBuf::from(CHROME_PATH);
+    let p1 = PathBuf::from(&config::get().paths.chrome);
     if p1.exists() { return Ok(p1); }
 
     if let Ok(output) = Command::new("which").arg("chromium").output() {
@@ -21,7 +22,7 @@ fn find_chromium_path() -> Result<PathBuf> {
 
 pub fn launch_browser() -> Result<Browser> {
     let termux_path = find_chromium_path()?;
-    let ua_arg = format!("--user-agent={}", USER_AGENT);
+    let ua_arg = format!("--user-agent={}", config::get().browser.user_agent);
     
     
     let random_id: u32 = rand::random();
@@ -32,9 +33,9 @@ pub fn launch_browser() -> Result<Browser> {
     let has_display = env::var("DISPLAY").is_ok();
     
     if has_display {
-        println!(" [DISPLAY DETECTED] Launching in X11 Visual Mode (Streaming)...");
+        info!("[DISPLAY DETECTED] Launching in X11 Visual Mode (Streaming)...");
     } else {
-        println!(" [NO DISPLAY DETECTED] Launching in Headless Mode (Invisible).");
+        info!("[NO DISPLAY DETECTED] Launching in Headless Mode (Invisible).");
     }
 
     let mut args_vec = vec![
@@ -65,11 +66,11 @@ pub fn launch_browser() -> Result<Browser> {
         path: Some(termux_path),
         window_size: Some((1280, 720)),
         enable_gpu: false,
-        args: args_vec.iter().map(|s| OsStr::new(s)).collect(),
+        args: args_vec.iter().map(OsStr::new).collect(),
         ..Default::default()
     };
 
-    println!("Initializing Termux Chromium Engine...");
+    info!("Initializing Termux Chromium Engine...");
     
     match Browser::new(options) {
         Ok(b) => Ok(b),
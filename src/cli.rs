@@ -0,0 +1,152 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "stov", version, about = "State of the Art Observation Tool")]
+pub struct Cli {
+    /// Path to stov.toml (default: $XDG_CONFIG_HOME/stov/stov.toml, then ./stov.toml)
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Log level (default: logging.level)
+    #[arg(long, global = true, value_parser = ["trace", "debug", "info", "warn", "error"])]
+    pub log_level: Option<String>,
+
+    /// Log output format (default: logging.format)
+    #[arg(long, global = true, value_parser = ["text", "json"])]
+    pub log_format: Option<String>,
+
+    /// Also write logs to rotating files in this directory (default: logging.dir)
+    #[arg(long, global = true)]
+    pub log_dir: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Log in and archive the stories of every target
+    Run(RunArgs),
+    /// Keep running and re-poll the targets on a schedule
+    Watch(WatchArgs),
+    /// Log in with credentials and save the session profile without scraping
+    Login(LoginArgs),
+    /// Verify saved sessions without scraping (exit code 0 = all valid, 1 = error, 2 = expired, 3 = checkpointed; an error takes precedence)
+    Check {
+        /// Profiles to check (all profiles when omitted)
+        names: Vec<String>,
+    },
+    /// Re-hash downloaded files and report missing, corrupted or untracked ones (exit code 1 on any problem)
+    Verify {
+        /// Targets to verify (all targets, plus an untracked-file scan, when omitted)
+        targets: Vec<String>,
+    },
+    /// Manage saved session profiles (lists them by default)
+    Profiles {
+        #[command(subcommand)]
+        action: Option<ProfileCommand>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProfileCommand {
+    /// List saved session profiles
+    List,
+    /// Convert plaintext profiles to the encrypted format (all profiles when no name is given)
+    Encrypt {
+        names: Vec<String>,
+    },
+    /// Delete a saved profile
+    Delete {
+        name: String,
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Rename a saved profile
+    Rename {
+        old: String,
+        new: String,
+    },
+    /// Export a profile to a passphrase-protected file for another device
+    Export {
+        name: String,
+        file: PathBuf,
+        /// Read the export passphrase from this environment variable instead of prompting
+        #[arg(long, value_name = "VAR")]
+        passphrase_env: Option<String>,
+    },
+    /// Import a profile exported on another device
+    Import {
+        file: PathBuf,
+        /// Save under this name instead of the exported one
+        #[arg(long)]
+        name: Option<String>,
+        /// Overwrite an existing profile with the same name
+        #[arg(long)]
+        force: bool,
+        /// Read the export passphrase from this environment variable instead of prompting
+        #[arg(long, value_name = "VAR")]
+        passphrase_env: Option<String>,
+    },
+}
+
+#[derive(Args, Default)]
+pub struct LoginArgs {
+    /// Instagram username (prompted when missing)
+    #[arg(short, long)]
+    pub username: Option<String>,
+
+    /// Instagram password (visible in the process list; prefer the other sources)
+    #[arg(short, long, group = "password_source")]
+    pub password: Option<String>,
+
+    /// Read the password from this environment variable
+    #[arg(long, value_name = "VAR", group = "password_source")]
+    pub password_env: Option<String>,
+
+    /// Read the password from a file only readable by the owner
+    #[arg(long, value_name = "PATH", group = "password_source")]
+    pub password_file: Option<String>,
+
+    /// Read the password from the first line printed by a command
+    #[arg(long, value_name = "CMD", group = "password_source")]
+    pub password_command: Option<String>,
+}
+
+#[derive(Args, Default)]
+pub struct RunArgs {
+    /// Reuse the session of a saved profile instead of logging in
+    #[arg(long, conflicts_with_all = ["username", "password_source"])]
+    pub profile: Option<String>,
+
+    #[command(flatten)]
+    pub login: LoginArgs,
+
+    /// Comma separated list of target usernames
+    #[arg(short, long, value_delimiter = ',')]
+    pub targets: Vec<String>,
+
+    /// File with one target per line, optionally followed by key=value options
+    #[arg(long)]
+    pub targets_file: Option<PathBuf>,
+
+    /// Also archive the story highlights of every target
+    #[arg(long)]
+    pub highlights: bool,
+}
+
+#[derive(Args)]
+pub struct WatchArgs {
+    #[command(flatten)]
+    pub run: RunArgs,
+
+    /// Time between cycles, e.g. 90s, 30m, 2h (default: watch.interval)
+    #[arg(long)]
+    pub interval: Option<String>,
+
+    /// Maximum random delay added to every interval (default: watch.jitter)
+    #[arg(long)]
+    pub jitter: Option<String>,
+}
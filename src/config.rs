@@ -1,23 +1,292 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use crate::credentials::CredentialSource;
+use crate::template::{Template, DEFAULT_TEMPLATE};
 
-pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/114.0.0.0 Safari/537.36";
+pub const CONFIG_FILE: &str = "stov.toml";
 
+static CONFIG: OnceLock<Config> = OnceLock::new();
 
-pub const CHROME_PATH: &str = "/data/data/com.termux/files/usr/bin/chromium";
-pub const DOWNLOAD_DIR: &str = "./downloads";
-pub const IMAGES_DIR: &str = "./images";
-pub const PROOF_DIR: &str = "./images/login_proofs";
-pub const ERROR_DIR: &str = "./images/story_errors";
-pub const PROFILES_DIR: &str = "./profiles";
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub paths: Paths,
+    pub browser: BrowserConfig,
+    pub selectors: Selectors,
+    pub watch: WatchConfig,
+    pub logging: LoggingConfig,
+    pub credentials: HashMap<String, CredentialSource>,
+    pub profiles: ProfilesConfig,
+    pub archive: ArchiveConfig,
+}
 
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Paths {
+    pub chrome: String,
+    pub download_dir: String,
+    pub images_dir: String,
+    pub proof_dir: String,
+    pub error_dir: String,
+    pub profiles_dir: String,
+}
 
-pub const USER_CSS: &str = "input[name='username']";
-pub const USER_XPATH_1: &str = "//input[contains(@aria-label, 'username') or contains(@aria-label, 'Mobile')]";
-pub const USER_XPATH_2: &str = "//input[@type='text']";
+impl Default for Paths {
+    fn default() -> Self {
+        Self {
+            chrome: "/data/data/com.termux/files/usr/bin/chromium".to_string(),
+            download_dir: "./downloads".to_string(),
+            images_dir: "./images".to_string(),
+            proof_dir: "./images/login_proofs".to_string(),
+            error_dir: "./images/story_errors".to_string(),
+            profiles_dir: "./profiles".to_string(),
+        }
+    }
+}
 
-pub const PASS_CSS: &str = "input[name='password']";
-pub const PASS_XPATH: &str = "//input[@type='password']";
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrowserConfig {
+    pub user_agent: String,
+}
 
-pub const SEL_SUBMIT: &str = "button[type='submit']";
-pub const SEL_HOME_ICON: &str = "svg[aria-label='Home']"; 
-pub const SEL_STORY_RING: &str = "canvas"; 
-pub const SEL_AVATAR: &str = "img[alt*='profile picture']";
+impl Default for BrowserConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/114.0.0.0 Safari/537.36".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Selectors {
+    pub user_css: String,
+    pub user_xpath_1: String,
+    pub user_xpath_2: String,
+    pub pass_css: String,
+    pub pass_xpath: String,
+    pub submit: String,
+    pub home_icon: String,
+    pub story_ring: String,
+    pub highlight: String,
+    pub avatar: String,
+}
+
+impl Default for Selectors {
+    fn default() -> Self {
+        Self {
+            user_css: "input[name='username']".to_string(),
+            user_xpath_1: "//input[contains(@aria-label, 'username') or contains(@aria-label, 'Mobile')]".to_string(),
+            user_xpath_2: "//input[@type='text']".to_string(),
+            pass_css: "input[name='password']".to_string(),
+            pass_xpath: "//input[@type='password']".to_string(),
+            submit: "button[type='submit']".to_string(),
+            home_icon: "svg[aria-label='Home']".to_string(),
+            story_ring: "canvas".to_string(),
+            highlight: "a[href*='/stories/highlights/']".to_string(),
+            avatar: "img[alt*='profile picture']".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    pub interval: String,
+    pub jitter: String,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            interval: "2h".to_string(),
+            jitter: "15m".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub level: String,
+    pub filter: String,
+    pub format: String,
+    pub dir: String,
+    pub rotation: String,
+    pub max_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            filter: String::new(),
+            format: "text".to_string(),
+            dir: String::new(),
+            rotation: "daily".to_string(),
+            max_files: 7,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfilesConfig {
+    pub encrypt: bool,
+    pub passphrase: CredentialSource,
+}
+
+impl Default for ProfilesConfig {
+    fn default() -> Self {
+        Self {
+            encrypt: false,
+            passphrase: CredentialSource::Prompt,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    pub database: String,
+    pub template: String,
+    pub sidecars: bool,
+    // `template`, parsed once by `Config::validate`.
+    #[serde(skip)]
+    pub file_template: Template,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            database: "./archive.db".to_string(),
+            template: DEFAULT_TEMPLATE.to_string(),
+            sidecars: false,
+            file_template: Template::parse(DEFAULT_TEMPLATE).expect("default template is valid"),
+        }
+    }
+}
+
+impl Config {
+    fn validate(&mut self) -> Result<()> {
+        let required = [
+            ("paths.chrome", &self.paths.chrome),
+            ("paths.download_dir", &self.paths.download_dir),
+            ("paths.images_dir", &self.paths.images_dir),
+            ("paths.proof_dir", &self.paths.proof_dir),
+            ("paths.error_dir", &self.paths.error_dir),
+            ("paths.profiles_dir", &self.paths.profiles_dir),
+            ("browser.user_agent", &self.browser.user_agent),
+            ("archive.database", &self.archive.database),
+            ("selectors.user_css", &self.selectors.user_css),
+            ("selectors.pass_css", &self.selectors.pass_css),
+            ("selectors.submit", &self.selectors.submit),
+            ("selectors.home_icon", &self.selectors.home_icon),
+            ("selectors.story_ring", &self.selectors.story_ring),
+            ("selectors.highlight", &self.selectors.highlight),
+            ("selectors.avatar", &self.selectors.avatar),
+        ];
+        for (key, value) in required {
+            if value.trim().is_empty() { return Err(anyhow!("`{}` must not be empty", key)); }
+        }
+
+        let xpaths = [
+            ("selectors.user_xpath_1", &self.selectors.user_xpath_1),
+            ("selectors.user_xpath_2", &self.selectors.user_xpath_2),
+            ("selectors.pass_xpath", &self.selectors.pass_xpath),
+        ];
+        for (key, value) in xpaths {
+            if !value.starts_with('/') && !value.starts_with('(') {
+                return Err(anyhow!("`{}` is not an XPath expression: {:?}", key, value));
+            }
+        }
+
+        let interval = parse_duration(&self.watch.interval).map_err(|e| anyhow!("`watch.interval`: {}", e))?;
+        if interval.is_zero() { return Err(anyhow!("`watch.interval` must be greater than zero")); }
+
+        let log = &self.logging;
+        if !["trace", "debug", "info", "warn", "error"].contains(&log.level.as_str()) {
+            return Err(anyhow!("`logging.level` must be one of trace, debug, info, warn, error (found {:?})", log.level));
+        }
+        if !["text", "json"].contains(&log.format.as_str()) {
+            return Err(anyhow!("`logging.format` must be \"text\" or \"json\" (found {:?})", log.format));
+        }
+        if !["hourly", "daily", "never"].contains(&log.rotation.as_str()) {
+            return Err(anyhow!("`logging.rotation` must be one of hourly, daily, never (found {:?})", log.rotation));
+        }
+        if log.max_files == 0 { return Err(anyhow!("`logging.max_files` must be greater than zero")); }
+
+        for (username, source) in &self.credentials {
+            source.validate(&format!("credentials.{}", username))?;
+        }
+        self.profiles.passphrase.validate("profiles.passphrase")?;
+        parse_duration(&self.watch.jitter).map_err(|e| anyhow!("`watch.jitter`: {}", e))?;
+        self.archive.file_template = Template::parse(&self.archive.template).map_err(|e| anyhow!("`archive.template`: {}", e))?;
+        Ok(())
+    }
+}
+
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+    let amount: u64 = digits.parse().map_err(|_| anyhow!("invalid duration {:?} (e.g. 90s, 30m, 2h)", value))?;
+    let scale = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(anyhow!("invalid duration unit in {:?} (expected s, m, h or d)", value)),
+    };
+    let secs = amount.checked_mul(scale).ok_or_else(|| anyhow!("duration {:?} is too large", value))?;
+    Ok(Duration::from_secs(secs))
+}
+
+fn find_config_file(explicit: Option<&Path>) -> Result<Option<PathBuf>> {
+    if let Some(path) = explicit {
+        if !path.exists() { return Err(anyhow!("Config file not found: {:?}", path)); }
+        return Ok(Some(path.to_path_buf()));
+    }
+
+    let xdg_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    let candidates = xdg_dir
+        .map(|dir| dir.join("stov").join(CONFIG_FILE))
+        .into_iter()
+        .chain(std::iter::once(PathBuf::from(CONFIG_FILE)));
+
+    for path in candidates {
+        if path.is_file() { return Ok(Some(path)); }
+    }
+    Ok(None)
+}
+
+pub fn load(explicit: Option<&Path>) -> Result<Option<PathBuf>> {
+    let path = find_config_file(explicit)?;
+
+    let config = match &path {
+        Some(p) => {
+            let data = fs::read_to_string(p).map_err(|e| anyhow!("Failed to read config {:?}: {}", p, e))?;
+            let mut config: Config = toml::from_str(&data).map_err(|e| anyhow!("Invalid config {:?}: {}", p, e))?;
+            config.validate().map_err(|e| anyhow!("Invalid config {:?}: {}", p, e))?;
+            config
+        },
+        None => Config::default(),
+    };
+
+    CONFIG.set(config).map_err(|_| anyhow!("Configuration already loaded"))?;
+    Ok(path)
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
@@ -0,0 +1,77 @@
+use anyhow::{Result, anyhow};
+use colored::*;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "source", rename_all = "lowercase", deny_unknown_fields)]
+pub enum CredentialSource {
+    Prompt,
+    Env { var: String },
+    File { path: String },
+    Command { command: String },
+}
+
+impl CredentialSource {
+    pub fn validate(&self, key: &str) -> Result<()> {
+        let (field, value) = match self {
+            CredentialSource::Prompt => return Ok(()),
+            CredentialSource::Env { var } => ("var", var),
+            CredentialSource::File { path } => ("path", path),
+            CredentialSource::Command { command } => ("command", command),
+        };
+        if value.trim().is_empty() { return Err(anyhow!("`{}.{}` must not be empty", key, field)); }
+        Ok(())
+    }
+
+    pub fn read(&self, label: &str) -> Result<String> {
+        let secret = match self {
+            CredentialSource::Prompt => prompt_hidden(label)?,
+            CredentialSource::Env { var } => env::var(var).map_err(|_| anyhow!("Environment variable {} is not set", var))?,
+            CredentialSource::File { path } => read_secret_file(Path::new(path))?,
+            CredentialSource::Command { command } => run_secret_command(command)?,
+        };
+        if secret.is_empty() { return Err(anyhow!("Credential source returned an empty secret")); }
+        Ok(secret)
+    }
+}
+
+pub fn prompt_hidden(label: &str) -> Result<String> {
+    let input = rpassword::prompt_password(format!("{} ", label.yellow()))?;
+    Ok(input.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn read_secret_file(path: &Path) -> Result<String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)
+            .map_err(|e| anyhow!("Failed to read credentials file {:?}: {}", path, e))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            return Err(anyhow!("Credentials file {:?} is accessible by other users (mode {:o}). Run: chmod 600 {:?}", path, mode & 0o777, path));
+        }
+    }
+
+    let data = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read credentials file {:?}: {}", path, e))?;
+    Ok(data.lines().next().unwrap_or("").to_string())
+}
+
+fn run_secret_command(command: &str) -> Result<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .map_err(|e| anyhow!("Failed to run credential command: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("Credential command exited with {}: {}", output.status, stderr.trim()));
+    }
+    let stdout = String::from_utf8(output.stdout).map_err(|_| anyhow!("Credential command printed invalid UTF-8"))?;
+    Ok(stdout.lines().next().unwrap_or("").to_string())
+}
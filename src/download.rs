@@ -0,0 +1,120 @@
+use anyhow::{Result, anyhow};
+use futures_util::StreamExt;
+use reqwest::header::{HeaderMap, HeaderValue, COOKIE, REFERER, USER_AGENT};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::config;
+use crate::profile::StoredCookie;
+use crate::utils::{sha256_file, sha256_hex, temp_path};
+
+pub struct TempMedia {
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+    keep: bool,
+}
+
+impl TempMedia {
+    fn create() -> Result<(Self, fs::File)> {
+        let dir = Path::new(&config::get().paths.download_dir);
+        fs::create_dir_all(dir)?;
+        let path = temp_path(dir);
+        let file = fs::OpenOptions::new().write(true).create_new(true).open(&path)?;
+        Ok((Self { path, size: 0, sha256: String::new(), keep: false }, file))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (mut temp, mut file) = Self::create()?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        temp.size = bytes.len() as u64;
+        temp.sha256 = sha256_hex(bytes);
+        Ok(temp)
+    }
+
+    pub fn from_writer(write: impl FnOnce(&mut fs::File) -> Result<()>) -> Result<Self> {
+        let (mut temp, mut file) = Self::create()?;
+        write(&mut file)?;
+        file.sync_all()?;
+        temp.size = file.metadata()?.len();
+        temp.sha256 = sha256_file(&temp.path)?;
+        Ok(temp)
+    }
+
+    pub fn persisted(mut self) {
+        self.keep = true;
+    }
+}
+
+impl Drop for TempMedia {
+    fn drop(&mut self) {
+        if !self.keep { let _ = fs::remove_file(&self.path); }
+    }
+}
+
+pub struct Downloader {
+    client: reqwest::Client,
+    cookies: Vec<StoredCookie>,
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    let domain = domain.trim_start_matches('.');
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+impl Downloader {
+    pub fn new(cookies: Vec<StoredCookie>) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_str(&config::get().browser.user_agent)?);
+        headers.insert(REFERER, HeaderValue::from_static("https://www.instagram.com/"));
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .connect_timeout(Duration::from_secs(15))
+            .timeout(Duration::from_secs(300))
+            .build()
+            .map_err(|e| anyhow!("Failed to build HTTP client: {}", e))?;
+        Ok(Self { client, cookies })
+    }
+
+    fn cookie_header(&self, url: &reqwest::Url) -> Option<String> {
+        let host = url.host_str()?;
+        let secure = url.scheme() == "https";
+        let pairs: Vec<String> = self.cookies
+            .iter()
+            .filter(|c| domain_matches(host, &c.domain) && url.path().starts_with(&c.path) && (secure || !c.secure) && !c.is_expired())
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect();
+        if pairs.is_empty() { None } else { Some(pairs.join("; ")) }
+    }
+
+    pub async fn fetch(&self, url: &str) -> Result<TempMedia> {
+        let url = reqwest::Url::parse(url)?;
+        let mut request = self.client.get(url.clone());
+        if let Some(cookies) = self.cookie_header(&url) { request = request.header(COOKIE, cookies); }
+
+        let response = request.send().await?.error_for_status()?;
+        let expected = response.content_length();
+
+        let (mut temp, mut file) = TempMedia::create()?;
+        let mut hasher = Sha256::new();
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            file.write_all(&chunk)?;
+            hasher.update(&chunk);
+            temp.size += chunk.len() as u64;
+        }
+        file.sync_all()?;
+
+        if let Some(expected) = expected {
+            if expected != temp.size {
+                return Err(anyhow!("Connection closed after {} of {} bytes", temp.size, expected));
+            }
+        }
+        temp.sha256 = format!("{:x}", hasher.finalize());
+        Ok(temp)
+    }
+}
@@ -1,22 +1,383 @@
-use headless_chrome::{Browser, Tab, Element, protocol::cdp::Network::CookieParam};
+use headless_chrome::{Browser, Tab, Element, protocol::cdp::Network};
+use std::fs;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH, Instant};
 use std::thread;
 use rand::Rng;
+use serde::Deserialize;
 use anyhow::{Result, anyhow};
-use std::collections::HashSet; 
-use crate::config::*;
-use crate::utils::{log_info, log_error, save_base64_file, save_screenshot, save_html, save_profile};
+use chrono::DateTime;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use crate::archive::{self, Archive, ArchiveEntry};
+use crate::config;
+use crate::manifest;
+use crate::sidecar::{self, Sidecar};
+use crate::media::{self, MediaInfo};
+use crate::mp4::{self, TrackKind};
+use crate::network::NetworkLog;
+use crate::overlay::Overlays;
+use crate::quality::{self, Quality};
+use crate::targets::Target;
+use crate::template::{sanitize, TemplateVars};
+use tracing::{debug, info, warn};
+use crate::profile::{save_profile, StoredCookie, UserProfile};
+use crate::download::{Downloader, TempMedia};
+use crate::utils::{SavedMedia, decode_data_uri, persist_media, save_screenshot, save_html, sha256_file};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SessionStatus {
+    Valid,
+    Expired,
+    Checkpointed,
+}
+
+impl SessionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionStatus::Valid => "valid",
+            SessionStatus::Expired => "expired",
+            SessionStatus::Checkpointed => "checkpointed",
+        }
+    }
+}
+
+enum SlideOutcome {
+    Saved,
+    Filtered,
+    Archived,
+    NotFound,
+}
 
 pub struct InstagramBot<'a> {
     _browser: &'a Browser,
     tab: Arc<Tab>,
+    network: NetworkLog,
+    archive: Option<Archive>,
+}
+
+const MIN_IMAGE_EDGE: u32 = 320;
+const MAX_AUDIO_PROBES: usize = 3;
+const AUDIO_DURATION_TOLERANCE: f64 = 0.5;
+
+fn strip_byte_range(url: &str) -> String {
+    if !url.contains(".mp4") { return url.to_string(); }
+    match url.find("&bytestart").or_else(|| url.find("?bytestart")) {
+        Some(idx) => url[..idx].to_string(),
+        None => url.to_string(),
+    }
+}
+
+fn is_audio_hint(url: &str) -> bool {
+    quality::efg(url).is_some_and(|efg| efg.contains("audio"))
+}
+
+#[derive(Deserialize)]
+struct Highlight {
+    id: String,
+    title: String,
+    cover: String,
+}
+
+#[derive(Clone, Default)]
+struct StoryMeta {
+    media_id: Option<String>,
+    taken_at: Option<i64>,
+    page_url: Option<String>,
+    alt: Option<String>,
+    position: usize,
+    overlays: Overlays,
+}
+
+struct Candidate<'a> {
+    source: &'a str,
+    url: String,
+    url_id: Option<String>,
+    mime: Option<String>,
+    bytes: Option<u64>,
+    quality: Quality,
+    // Set by rank_candidates when the media id matches the visible slide.
+    shown: bool,
+}
+
+impl Candidate<'_> {
+    fn ext(&self) -> &'static str {
+        match self.mime.as_deref() {
+            Some(mime) if mime.starts_with("video/") || mime.starts_with("audio/") => "mp4",
+            Some(_) => "jpg",
+            None if self.url.contains(".mp4") => "mp4",
+            None => "jpg",
+        }
+    }
+
+    fn is_audio(&self) -> bool {
+        self.mime.as_deref().is_some_and(|m| m.starts_with("audio/")) || is_audio_hint(&self.url)
+    }
+
+    fn describe(&self) -> String {
+        match self.bytes {
+            Some(bytes) => format!("{} via {} ({} KB)", self.quality, self.source, bytes / 1024),
+            None => format!("{} via {}", self.quality, self.source),
+        }
+    }
+}
+
+// Media on screen first, then the slide's media type, then resolution, bitrate and transfer size.
+// A main <video> element makes it a video slide even when it plays from a blob: URL, so a poster image can't outrank the streams.
+fn rank_candidates(candidates: &mut [Candidate], slide_id: Option<&str>, video_on_screen: bool) {
+    let video_first = video_on_screen || !candidates.iter().any(|c| c.source == "DOM_IMAGE");
+    let mut on_screen: HashSet<String> = candidates.iter()
+        .filter(|c| c.source != "NET" && (c.ext() == "mp4") == video_first)
+        .filter_map(|c| c.url_id.clone())
+        .collect();
+    on_screen.extend(slide_id.map(str::to_string));
+    for c in candidates.iter_mut() { c.shown = c.url_id.as_ref().is_some_and(|id| on_screen.contains(id)); }
+    candidates.sort_by_key(|c| Reverse((c.shown, (c.ext() == "mp4") == video_first, !c.is_audio(), c.quality, c.bytes)));
+}
+
+struct AudioTrack {
+    url: String,
+    temp: TempMedia,
+    info: MediaInfo,
+}
+
+// An audio stream belongs to a video when both carry the same asset id, or failing that, the same duration.
+fn audio_matches(video_url: &str, video: &MediaInfo, audio_url: &str, audio: &MediaInfo) -> bool {
+    if let (Some(v), Some(a)) = (quality::asset_id(video_url), quality::asset_id(audio_url)) { return v == a; }
+    match (video.duration, audio.duration) {
+        (Some(v), Some(a)) => (v - a).abs() <= AUDIO_DURATION_TOLERANCE,
+        _ => false,
+    }
+}
+
+fn media_pk_from_cache_key(url: &str) -> Option<String> {
+    let query = url.split_once('?')?.1;
+    let value = query.split('&').find_map(|kv| kv.strip_prefix("ig_cache_key="))?;
+    let decoded = quality::decode_param(value.split('.').next()?)?;
+    let pk = String::from_utf8(decoded).ok()?;
+    if pk.is_empty() || !pk.chars().all(|c| c.is_ascii_digit()) { return None; }
+    Some(pk)
+}
+
+fn media_id_from_url(url: &str) -> Option<String> {
+    if let Some(pk) = media_pk_from_cache_key(url) { return Some(pk); }
+    let path = url.split('?').next()?;
+    let name = path.rsplit('/').next()?;
+    let stem = name.split('.').next()?;
+    if stem.len() < 8 { return None; }
+    Some(stem.to_string())
+}
+
+fn file_name(target: &Target, vars: &TemplateVars) -> String {
+    let name = target.template.as_ref().unwrap_or(&config::get().archive.file_template).render(vars);
+    match &target.folder {
+        Some(folder) => format!("{}/{}", folder, name),
+        None => name,
+    }
 }
 
 impl<'a> InstagramBot<'a> {
     pub fn new(browser: &'a Browser) -> Result<Self> {
         let tab = browser.new_tab()?;
-        Ok(Self { _browser: browser, tab })
+        let network = NetworkLog::attach(&tab)?;
+        Ok(Self { _browser: browser, tab, network, archive: None })
+    }
+
+    pub fn with_archive(mut self, archive: Archive) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    fn record_download(&self, username: &str, url: &str, source: &str, meta: &StoryMeta, captured_at: i64, saved: &SavedMedia) {
+        if !meta.overlays.is_empty() { info!("Captured overlays: {}", meta.overlays.summary()); }
+        if let Err(e) = manifest::record(username, saved) {
+            warn!("Failed to update manifest for {}: {}", username, e);
+        }
+        if config::get().archive.sidecars {
+            let sidecar = Sidecar {
+                target: username,
+                media_id: meta.media_id.as_deref(),
+                taken_at: meta.taken_at,
+                captured_at,
+                source,
+                url,
+                page_url: meta.page_url.as_deref(),
+                position: meta.position,
+                mime: saved.mime,
+                width: saved.info.width,
+                height: saved.info.height,
+                duration: saved.info.duration,
+                size: saved.size,
+                sha256: &saved.sha256,
+                alt: meta.alt.as_deref(),
+                overlays: &meta.overlays,
+            };
+            if let Err(e) = sidecar::write(Path::new(&saved.path), &sidecar) {
+                warn!("Failed to write sidecar for {}: {}", saved.path, e);
+            }
+        }
+        let Some(archive) = &self.archive else { return };
+        let overlays = if meta.overlays.is_empty() { None } else { serde_json::to_string(&meta.overlays).ok() };
+        let dedup_key = archive::dedup_key(meta.media_id.as_deref(), &saved.sha256);
+        let entry = ArchiveEntry {
+            target: username,
+            media_id: meta.media_id.as_deref(),
+            url,
+            source,
+            taken_at: meta.taken_at,
+            captured_at,
+            path: &saved.path,
+            size: saved.size,
+            sha256: &saved.sha256,
+            mime: saved.mime,
+            width: saved.info.width,
+            height: saved.info.height,
+            duration: saved.info.duration,
+            overlays: overlays.as_deref(),
+            dedup_key: &dedup_key,
+        };
+        if let Err(e) = archive.record(&entry) {
+            warn!("Failed to record download in archive: {}", e);
+        }
+    }
+
+    async fn fetch_media(&self, downloader: Option<&Downloader>, url: &str) -> Result<TempMedia> {
+        if let Some(downloader) = downloader {
+            match downloader.fetch(url).await {
+                Ok(temp) => return Ok(temp),
+                Err(e) => debug!("Streaming download failed ({}), falling back to in-page fetch", e),
+            }
+        }
+        self.fetch_in_page(url)
+    }
+
+    fn fetch_in_page(&self, url: &str) -> Result<TempMedia> {
+        let js_fetch = format!(r#"
+            (async function() {{
+                try {{
+                    const response = await fetch("{}", {{ cache: 'force-cache' }});
+                    const blob = await response.blob();
+                    return await new Promise((resolve) => {{
+                        const reader = new FileReader();
+                        reader.onloadend = () => resolve(reader.result);
+                        reader.readAsDataURL(blob);
+                    }});
+                }} catch (err) {{ return "ERROR"; }}
+            }})()
+        "#, url);
+
+        let result = self.tab.evaluate(&js_fetch, true)?;
+        let data_uri = result.value.as_ref().and_then(|v| v.as_str()).unwrap_or("");
+        if !data_uri.starts_with("data:") { return Err(anyhow!("In-page fetch failed")); }
+        TempMedia::from_bytes(&decode_data_uri(data_uri)?)
+    }
+
+    async fn find_audio_track(&self, downloader: Option<&Downloader>, video_url: &str, video: &MediaInfo, candidates: &[&String]) -> Option<TempMedia> {
+        let asset = quality::asset_id(video_url);
+        let mut ordered: Vec<&String> = candidates.to_vec();
+        ordered.sort_by_key(|url| (asset.is_none() || quality::asset_id(url) != asset, !is_audio_hint(url)));
+        for url in ordered.into_iter().take(MAX_AUDIO_PROBES) {
+            let Ok(temp) = self.fetch_media(downloader, url).await else { continue };
+            if !matches!(mp4::track_kinds(&temp.path).as_deref(), Ok([TrackKind::Audio])) { continue; }
+            let Ok(info) = fs::File::open(&temp.path).map_err(media::Rejection::from).and_then(|mut f| media::inspect(&mut f)) else { continue };
+            if audio_matches(video_url, video, url, &info) { return Some(temp); }
+            debug!("Audio stream {} does not belong to this video", url);
+        }
+        None
+    }
+
+    async fn add_audio_track(&self, downloader: Option<&Downloader>, video_url: &str, video: TempMedia, info: MediaInfo, seen: &mut Vec<AudioTrack>, candidates: &[&String]) -> (TempMedia, MediaInfo) {
+        let audio = match seen.iter().position(|a| audio_matches(video_url, &info, &a.url, &a.info)) {
+            Some(idx) => Some(seen.swap_remove(idx).temp),
+            None => self.find_audio_track(downloader, video_url, &info, candidates).await,
+        };
+        let Some(audio) = audio else {
+            debug!("No matching audio track found for this video.");
+            return (video, info);
+        };
+
+        let muxed = TempMedia::from_writer(|file| mp4::mux(&video.path, &audio.path, file)).and_then(|temp| {
+            let info = media::inspect(&mut fs::File::open(&temp.path)?)?;
+            Ok((temp, info))
+        });
+        match muxed {
+            Ok(result) => {
+                info!("Merged separate video and audio tracks.");
+                result
+            },
+            Err(e) => {
+                warn!("Failed to merge audio track, keeping silent video: {}", e);
+                (video, info)
+            }
+        }
+    }
+
+    fn is_archived(&self, key: Option<&str>) -> bool {
+        let (Some(archive), Some(key)) = (&self.archive, key) else { return false };
+        archive.contains_key(key).unwrap_or_else(|e| { warn!("Archive lookup failed: {}", e); false })
+    }
+
+    fn story_meta(&self, username: &str) -> StoryMeta {
+        let js_meta = r#"
+            (function() {
+                const visible = (el) => { const r = el.getBoundingClientRect(); return r.width > 0 && r.height > 0; };
+                const m = location.pathname.match(/\/stories\/(?!highlights\/)[^\/]+\/(\d+)/)
+                    || (new URLSearchParams(location.search).get('story_media_id') || '').match(/^(\d+)/);
+                const time = Array.from(document.querySelectorAll('time[datetime]')).find(visible);
+                const img = Array.from(document.querySelectorAll('img[alt]'))
+                    .find(i => visible(i) && i.getBoundingClientRect().width >= 200 && !i.alt.includes('profile'));
+
+                const area = (el) => { const r = el.getBoundingClientRect(); return r.width * r.height; };
+                const media = Array.from(document.querySelectorAll('video, img')).filter(visible).sort((a, b) => area(b) - area(a))[0];
+                const box = media ? media.getBoundingClientRect() : null;
+                const over = (el) => {
+                    if (!box) return false;
+                    const r = el.getBoundingClientRect();
+                    return r.right > box.left && r.left < box.right && r.bottom > box.top && r.top < box.bottom;
+                };
+                const overlays = { mentions: [], hashtags: [], links: [], locations: [], music: null };
+                for (const a of document.querySelectorAll('a[href]')) {
+                    if (!visible(a) || !over(a)) continue;
+                    const url = new URL(a.href, location.href);
+                    const text = (a.innerText || a.getAttribute('aria-label') || '').trim();
+                    let p;
+                    if (url.hostname === 'l.instagram.com') overlays.links.push(url.searchParams.get('u') || a.href);
+                    else if (!url.hostname.endsWith('instagram.com')) overlays.links.push(a.href);
+                    else if ((p = url.pathname.match(/^\/explore\/tags\/([^\/]+)/))) overlays.hashtags.push(decodeURIComponent(p[1]));
+                    else if ((p = url.pathname.match(/^\/explore\/locations\/(\d+)/))) overlays.locations.push({ id: p[1], name: text });
+                    else if ((p = url.pathname.match(/^\/reels?\/audio\/(\d+)/))) overlays.music = { id: p[1], title: text };
+                    else if ((p = url.pathname.match(/^\/([A-Za-z0-9._]+)\/?$/))) overlays.mentions.push(p[1]);
+                }
+
+                return JSON.stringify({
+                    pk: m ? m[1] : '',
+                    time: time ? time.getAttribute('datetime') : '',
+                    alt: img ? img.alt : '',
+                    page: location.href,
+                    overlays: overlays,
+                });
+            })()
+        "#;
+        let raw = match self.tab.evaluate(js_meta, false) {
+            Ok(res) => res.value.and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default(),
+            Err(_) => String::new(),
+        };
+        let json: serde_json::Value = serde_json::from_str(&raw).unwrap_or_default();
+        let field = |key: &str| json[key].as_str().filter(|v| !v.is_empty()).map(str::to_string);
+        StoryMeta {
+            media_id: field("pk"),
+            taken_at: field("time").and_then(|t| DateTime::parse_from_rfc3339(&t).ok()).map(|t| t.timestamp()),
+            page_url: field("page"),
+            alt: field("alt"),
+            position: 0,
+            overlays: Overlays::from_json(&json["overlays"], username),
+        }
+    }
+
+    fn is_archived_content(&self, sha256: &str) -> bool {
+        let Some(archive) = &self.archive else { return false };
+        archive.contains_hash(sha256).unwrap_or_else(|e| { warn!("Archive lookup failed: {}", e); false })
     }
 
     fn smart_find(&self, css: &str, xpath1: &str, xpath2: Option<&str>) -> Result<Element<'_>> {
@@ -42,31 +403,13 @@ impl<'a> InstagramBot<'a> {
         Ok(())
     }
 
-    fn inject_sniffer(&self) {
-        let script = r#"
-            if (!window.__sniffer_active) {
-                window.__intercepted_urls = [];
-                const observer = new PerformanceObserver((list) => {
-                    list.getEntries().forEach((entry) => {
-                        if (entry.name.includes('.mp4') || (entry.name.includes('.jpg') && entry.name.includes('instagram'))) {
-                            window.__intercepted_urls.push(entry.name);
-                        }
-                    });
-                });
-                observer.observe({ entryTypes: ['resource'] });
-                window.__sniffer_active = true;
-            }
-        "#;
-        let _ = self.tab.evaluate(script, false);
-    }
-
     fn clear_network_logs(&self) {
-        let script = "window.__intercepted_urls = []; performance.clearResourceTimings();";
-        let _ = self.tab.evaluate(script, false);
+        self.network.clear();
     }
 
     fn safely_click_login(&self) -> Result<()> {
-        log_info("Activating Login...");
+        info!("Activating Login...");
+        let sel = &config::get().selectors;
         if let Ok(buttons) = self.tab.find_elements("button") {
             for btn in buttons {
                 if let Ok(text) = btn.get_inner_text() {
@@ -79,7 +422,7 @@ impl<'a> InstagramBot<'a> {
                 }
             }
         }
-        if let Ok(btn) = self.tab.find_element(SEL_SUBMIT) {
+        if let Ok(btn) = self.tab.find_element(&sel.submit) {
              let text = btn.get_inner_text().unwrap_or_default().to_lowercase();
              if !text.contains("show") {
                  let _ = btn.click();
@@ -90,48 +433,68 @@ impl<'a> InstagramBot<'a> {
         Ok(())
     }
 
-    pub fn login_with_session(&self, session_id: &str) -> Result<()> {
-        log_info("Attempting Login via Saved Session...");
+    fn browser_cookies(&self) -> Result<Vec<StoredCookie>> {
+        let cookies = self.tab.call_method(Network::GetAllCookies(None))?.cookies;
+        Ok(cookies.iter().map(StoredCookie::from).collect())
+    }
+
+    pub fn export_cookies(&self) -> Result<Vec<StoredCookie>> {
+        let cookies = self.tab.get_cookies()?;
+        Ok(cookies
+            .iter()
+            .filter(|c| c.domain.ends_with("instagram.com"))
+            .map(StoredCookie::from)
+            .collect())
+    }
+
+    fn inject_session(&self, profile: &UserProfile) -> Result<SessionStatus> {
+        let sel = &config::get().selectors;
+        self.tab.call_method(Network::ClearBrowserCookies(None))?;
         self.tab.navigate_to("https://www.instagram.com")?;
-        
-        let cookie = CookieParam {
-            name: "sessionid".to_string(),
-            value: session_id.to_string(),
-            url: Some("https://www.instagram.com".to_string()),
-            domain: Some(".instagram.com".to_string()),
-            path: Some("/".to_string()),
-            secure: Some(true),
-            http_only: Some(true),
-            same_site: None,
-            expires: None,
-            priority: None,
-            source_scheme: None,
-            source_port: None,
-            partition_key: None,
-            same_party: None,
-        };
-        
-        self.tab.set_cookies(vec![cookie])?;
-        log_info("Session cookie injected.");
+
+        let cookies: Vec<_> = profile.cookies.iter().filter(|c| !c.is_expired()).map(StoredCookie::to_param).collect();
+        let count = cookies.len();
+        self.tab.set_cookies(cookies)?;
+        debug!("{} session cookies injected.", count);
         self.tab.reload(true, None)?;
         
-        log_info("Verifying Session...");
+        info!("Verifying Session...");
         thread::sleep(Duration::from_secs(5));
-        
-        if self.tab.find_element(SEL_HOME_ICON).is_ok() || self.tab.find_element(SEL_AVATAR).is_ok() {
-            log_info("Session Login Successful!");
-            return Ok(());
+
+        let url = self.tab.get_url();
+        if url.contains("challenge") || url.contains("checkpoint") { return Ok(SessionStatus::Checkpointed); }
+        if self.tab.find_element(&sel.home_icon).is_ok() || self.tab.find_element(&sel.avatar).is_ok() {
+            return Ok(SessionStatus::Valid);
         }
         if let Ok(el) = self.tab.find_element_by_xpath("//button[contains(text(), 'Not Now')]") {
              let _ = el.click();
-             log_info("Session Login Successful (Popup dismissed).");
-             return Ok(());
+             debug!("Popup dismissed.");
+             return Ok(SessionStatus::Valid);
+        }
+        Ok(SessionStatus::Expired)
+    }
+
+    pub fn login_with_session(&self, profile: &UserProfile) -> Result<()> {
+        info!("Attempting Login via Saved Session...");
+        match self.inject_session(profile)? {
+            SessionStatus::Valid => {
+                info!("Session Login Successful!");
+                Ok(())
+            },
+            SessionStatus::Checkpointed => Err(anyhow!("Session is blocked by a security checkpoint. Complete it in the app, then login manually.")),
+            SessionStatus::Expired => Err(anyhow!("Session Expired or Invalid. Please login manually.")),
         }
-        Err(anyhow!("Session Expired or Invalid. Please login manually."))
+    }
+
+    pub fn check_session(&self, profile: &UserProfile) -> Result<SessionStatus> {
+        info!("Checking session for {}...", profile.username);
+        self.inject_session(profile)
     }
 
     pub fn login(&self, user: &str, pass: &str) -> Result<()> {
-        log_info("Navigating directly to Login Page...");
+        info!("Navigating directly to Login Page...");
+        let cfg = config::get();
+        let sel = &cfg.selectors;
         self.tab.navigate_to("https://www.instagram.com/accounts/login/")?;
         thread::sleep(Duration::from_secs(6)); 
 
@@ -140,45 +503,40 @@ impl<'a> InstagramBot<'a> {
             if let Ok(el) = self.tab.find_element_by_xpath(xpath) { let _ = el.click(); thread::sleep(Duration::from_secs(1)); break; }
         }
         
-        log_info("Inputting Credentials...");
-        match self.smart_find(USER_CSS, USER_XPATH_1, Some(USER_XPATH_2)) {
-            Ok(u_el) => { if let Err(e) = self.react_type(&u_el, user) { log_error(&format!("User Type Error: {}", e)); } },
-            Err(e) => { self.snapshot(ERROR_DIR, "missing_username"); return Err(e); }
+        info!("Inputting Credentials...");
+        match self.smart_find(&sel.user_css, &sel.user_xpath_1, Some(&sel.user_xpath_2)) {
+            Ok(u_el) => { if let Err(e) = self.react_type(&u_el, user) { warn!("User Type Error: {}", e); } },
+            Err(e) => { self.snapshot(&cfg.paths.error_dir, "missing_username"); return Err(e); }
         }
         thread::sleep(Duration::from_millis(500));
-        match self.smart_find(PASS_CSS, PASS_XPATH, None) {
-            Ok(p_el) => { if let Err(e) = self.react_type(&p_el, pass) { log_error(&format!("Pass Type Error: {}", e)); } },
-            Err(e) => { self.snapshot(ERROR_DIR, "missing_password"); return Err(e); }
+        match self.smart_find(&sel.pass_css, &sel.pass_xpath, None) {
+            Ok(p_el) => { if let Err(e) = self.react_type(&p_el, pass) { warn!("Pass Type Error: {}", e); } },
+            Err(e) => { self.snapshot(&cfg.paths.error_dir, "missing_password"); return Err(e); }
         }
         thread::sleep(Duration::from_secs(2));
 
         for attempt in 1..=3 {
-            if attempt > 1 { log_info(&format!("Retry attempt {}/3...", attempt)); }
-            if let Err(e) = self.safely_click_login() { log_error(&format!("Click failed: {}", e)); }
+            if attempt > 1 { warn!("Retry attempt {}/3...", attempt); }
+            if let Err(e) = self.safely_click_login() { warn!("Click failed: {}", e); }
 
-            log_info("Verifying authentication...");
+            info!("Verifying authentication...");
             let start_time = Instant::now();
             let mut retry_needed = false;
 
             while start_time.elapsed() < Duration::from_secs(20) {
                 let mut success = false;
-                if self.tab.find_element(SEL_HOME_ICON).is_ok() || self.tab.find_element(SEL_AVATAR).is_ok() { success = true; }
+                if self.tab.find_element(&sel.home_icon).is_ok() || self.tab.find_element(&sel.avatar).is_ok() { success = true; }
                 if let Ok(el) = self.tab.find_element_by_xpath("//button[contains(text(), 'Not Now')]") { let _ = el.click(); success = true; }
                 if !self.tab.get_url().contains("accounts/login") && !self.tab.get_url().contains("challenge") { success = true; }
 
                 if success {
-                    log_info("Login Verified.");
-                    log_info("Extracting Session ID...");
-                    if let Ok(cookies) = self.tab.get_cookies() {
-                        for c in cookies {
-                            if c.name == "sessionid" {
-                                let _ = save_profile(user, &c.value);
-                                log_info("Profile saved to profiles/ folder.");
-                                break;
-                            }
-                        }
+                    info!("Login Verified.");
+                    debug!("Extracting Session Cookies...");
+                    match self.export_cookies().and_then(|cookies| save_profile(user, cookies)) {
+                        Ok(()) => info!("Profile saved to profiles/ folder."),
+                        Err(e) => warn!("Failed to save profile: {}", e),
                     }
-                    self.snapshot(PROOF_DIR, "login_success");
+                    self.snapshot(&cfg.paths.proof_dir, "login_success");
                     return Ok(());
                 }
 
@@ -192,7 +550,7 @@ impl<'a> InstagramBot<'a> {
             }
 
             if retry_needed {
-                log_info("Waiting 3 seconds before retrying click...");
+                info!("Waiting 3 seconds before retrying click...");
                 thread::sleep(Duration::from_secs(3));
                 continue; 
             } else if attempt == 3 {
@@ -202,79 +560,189 @@ impl<'a> InstagramBot<'a> {
         Err(anyhow!("Login failed after retries"))
     }
 
-    pub async fn process_targets(&self, targets: Vec<String>) -> Result<()> {
+    pub async fn process_targets(&self, targets: &[Target]) -> Result<()> {
+        let sel = &config::get().selectors;
+        let downloader = self.browser_cookies()
+            .and_then(Downloader::new)
+            .map_err(|e| warn!("Streaming downloads unavailable, using in-page fetch: {}", e))
+            .ok();
         for target in targets {
-            log_info(&format!("Checking target: {}", target));
-            let url = format!("https://www.instagram.com/{}/", target);
-            if let Err(_) = self.tab.navigate_to(&url) { continue; }
+            if !target.enabled {
+                info!("Skipping disabled target: {}", target.username);
+                continue;
+            }
+            info!("Checking target: {}", target.username);
+            let url = format!("https://www.instagram.com/{}/", target.username);
+            if self.tab.navigate_to(&url).is_err() { continue; }
             thread::sleep(Duration::from_secs(5)); 
 
-            if self.tab.find_element(SEL_STORY_RING).is_ok() {
-                log_info("Story found! Starting batch download...");
-                let _ = self.process_story_batch(&target).await;
+            if self.tab.find_element(&sel.story_ring).is_ok() {
+                info!("Story found! Starting batch download...");
+                let _ = self.process_story_batch(target, downloader.as_ref(), &sel.story_ring, &target.username).await;
             } else {
-                log_info("No stories found for this user.");
+                info!("No stories found for this user.");
+            }
+            if target.highlights {
+                if let Err(e) = self.process_highlights(target, downloader.as_ref()).await {
+                    warn!("Highlights failed for {}: {}", target.username, e);
+                }
             }
             thread::sleep(Duration::from_secs(rand::thread_rng().gen_range(3..6)));
         }
         Ok(())
     }
 
-    async fn process_story_batch(&self, username: &str) -> Result<()> {
-        self.inject_sniffer();
+    fn list_highlights(&self) -> Vec<Highlight> {
+        let js_list = format!(r#"
+            (function() {{
+                const seen = new Set();
+                const found = [];
+                for (const a of document.querySelectorAll({:?})) {{
+                    const m = (a.getAttribute('href') || '').match(/highlights\/(\d+)/);
+                    if (!m || seen.has(m[1])) continue;
+                    seen.add(m[1]);
+                    const img = a.querySelector('img');
+                    found.push({{
+                        id: m[1],
+                        title: (a.innerText || '').trim() || (img ? img.alt : ''),
+                        cover: img ? (img.currentSrc || img.src) : '',
+                    }});
+                }}
+                return JSON.stringify(found);
+            }})()
+        "#, config::get().selectors.highlight);
+        let raw = match self.tab.evaluate(&js_list, false) {
+            Ok(res) => res.value.and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default(),
+            Err(_) => String::new(),
+        };
+        serde_json::from_str(&raw).unwrap_or_default()
+    }
+
+    async fn process_highlights(&self, target: &Target, downloader: Option<&Downloader>) -> Result<()> {
+        let url = format!("https://www.instagram.com/{}/", target.username);
+        self.tab.navigate_to(&url)?;
+        thread::sleep(Duration::from_secs(5));
+
+        let highlights = self.list_highlights();
+        if highlights.is_empty() {
+            info!("No highlights found for this user.");
+            return Ok(());
+        }
+        info!("Found {} highlights for {}.", highlights.len(), target.username);
+
+        for highlight in highlights {
+            let title = if highlight.title.trim().is_empty() { highlight.id.clone() } else { sanitize(highlight.title.trim()) };
+            let base = target.folder.as_deref().unwrap_or(&target.username);
+            let reel = Target { folder: Some(format!("{}/highlights/{}", base, title)), ..target.clone() };
+
+            self.tab.navigate_to(&url)?;
+            thread::sleep(Duration::from_secs(5));
+            if !highlight.cover.is_empty() {
+                if let Err(e) = self.save_highlight_cover(&reel, downloader, &highlight.cover).await {
+                    warn!("Failed to save cover of highlight '{}': {}", title, e);
+                }
+            }
+
+            info!("Opening highlight: {}", title);
+            let opener = format!("a[href*='/stories/highlights/{}/']", highlight.id);
+            let marker = format!("highlights/{}", highlight.id);
+            let _ = self.process_story_batch(&reel, downloader, &opener, &marker).await;
+            thread::sleep(Duration::from_secs(rand::thread_rng().gen_range(2..5)));
+        }
+        Ok(())
+    }
+
+    async fn save_highlight_cover(&self, reel: &Target, downloader: Option<&Downloader>, url: &str) -> Result<()> {
+        let folder = reel.folder.as_deref().unwrap_or_default();
+        let temp = self.fetch_media(downloader, url).await?;
+        let dir = Path::new(&config::get().paths.download_dir).join(folder);
+        if let Ok(entries) = fs::read_dir(&dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let is_cover = path.file_stem().and_then(|s| s.to_str()).is_some_and(|s| s.starts_with("cover"));
+                if is_cover && sha256_file(&path).is_ok_and(|hash| hash == temp.sha256) {
+                    debug!("Highlight cover unchanged.");
+                    return Ok(());
+                }
+            }
+        }
+        let info = media::inspect(&mut fs::File::open(&temp.path)?)?;
+        let saved = persist_media(temp, info, &format!("{}/cover.{}", folder, info.kind.extension()))?;
+        manifest::record(&reel.username, &saved)
+    }
+
+    async fn process_story_batch(&self, target: &Target, downloader: Option<&Downloader>, opener: &str, marker: &str) -> Result<()> {
+        let username = target.username.as_str();
+        if let Err(e) = self.network.start(&self.tab) { warn!("Network capture unavailable, using page elements only: {}", e); }
         
-        if let Ok(el) = self.tab.find_element(SEL_STORY_RING) { let _ = el.click(); }
+        if let Ok(el) = self.tab.find_element(opener) { let _ = el.click(); }
         thread::sleep(Duration::from_secs(3));
 
-        let mut downloaded_history: HashSet<String> = HashSet::new();
         let mut story_count = 0;
         let mut consecutive_errors = 0;
+        let mut slide = 0;
 
-        log_info(&format!("Starting batch extraction for: {}", username));
-        self.clear_network_logs();
+        info!("Starting batch extraction for: {}", username);
 
         loop {
             let current_url = self.tab.get_url();
-            if !current_url.contains("stories") { log_info("Batch ended (Returned to feed)."); break; }
-            if !current_url.contains(username) { log_info("Batch ended (Moved to different user)."); let _ = self.tab.press_key("Escape"); break; }
+            if !current_url.contains("stories") { info!("Batch ended (Returned to feed)."); break; }
+            if !current_url.contains(marker) { info!("Batch ended (Moved to a different reel)."); let _ = self.tab.press_key("Escape"); break; }
+            slide += 1;
 
-            match self.download_active_story(username, &mut downloaded_history).await {
-                Ok(true) => {
+            match self.download_active_story(target, downloader, slide).await {
+                Ok(SlideOutcome::Saved) => {
                     story_count += 1;
                     consecutive_errors = 0;
-                    log_info(&format!("Story #{} Saved.", story_count));
+                    info!("Story #{} Saved.", story_count);
                     self.clear_network_logs(); 
-                    log_info("Moving to next...");
+                    debug!("Moving to next...");
                     let _ = self.tab.press_key("ArrowRight");
                     thread::sleep(Duration::from_millis(1500));
                 },
-                Ok(false) => {
+                Ok(SlideOutcome::Filtered) => {
+                    consecutive_errors = 0;
+                    info!("Skipping (Media type excluded for this target)...");
+                    let _ = self.tab.press_key("ArrowRight");
+                    thread::sleep(Duration::from_millis(1500));
+                },
+                Ok(SlideOutcome::Archived) => {
+                    consecutive_errors = 0;
+                    info!("Skipping (Already archived)...");
+                    self.clear_network_logs();
+                    let _ = self.tab.press_key("ArrowRight");
+                    thread::sleep(Duration::from_millis(1500));
+                },
+                Ok(SlideOutcome::NotFound) => {
                     consecutive_errors += 1;
-                    log_info("Skipping (No new media found)...");
+                    info!("Skipping (No new media found)...");
                     let _ = self.tab.press_key("ArrowRight");
                     thread::sleep(Duration::from_millis(1500));
                 },
                 Err(e) => {
                     consecutive_errors += 1;
-                    log_error(&format!("Error: {}", e));
+                    warn!("Error: {}", e);
                     let _ = self.tab.press_key("ArrowRight");
                     thread::sleep(Duration::from_millis(1500));
                 }
             }
 
             if consecutive_errors > 8 {
-                log_info("Too many consecutive errors. Exiting batch.");
+                warn!("Too many consecutive errors. Exiting batch.");
                 let _ = self.tab.press_key("Escape");
                 break;
             }
         }
-        log_info(&format!("Batch complete. Total saved: {}", story_count));
+        info!("Batch complete. Total saved: {}", story_count);
+        if let Err(e) = self.network.stop(&self.tab) { debug!("Failed to stop network capture: {}", e); }
         Ok(())
     }
 
-    async fn download_active_story(&self, username: &str, history: &mut HashSet<String>) -> Result<bool> {
+    async fn download_active_story(&self, target: &Target, downloader: Option<&Downloader>, position: usize) -> Result<SlideOutcome> {
+        let username = target.username.as_str();
 
         let mut failed_urls_this_slide: HashSet<String> = HashSet::new();
+        let mut audio_tracks: Vec<AudioTrack> = Vec::new();
 
         for _attempt in 1..=20 { 
             
@@ -292,6 +760,12 @@ impl<'a> InstagramBot<'a> {
             "#;
             let _ = self.tab.evaluate(js_freeze, false);
 
+            let meta = StoryMeta { position, ..self.story_meta(username) };
+            if self.is_archived(meta.media_id.as_deref()) {
+                debug!("Story {} already archived.", meta.media_id.as_deref().unwrap_or(""));
+                return Ok(SlideOutcome::Archived);
+            }
+
 
             let js_identify = r#"
                 (function() {
@@ -311,107 +785,274 @@ impl<'a> InstagramBot<'a> {
                         return isCentered;
                     }
 
-                    let urls = window.__intercepted_urls || [];
                     let candidates = [];
                     
                     
-                    for (let i = urls.length - 1; i >= 0; i--) { candidates.push("NET|" + urls[i]); }
-                    
-                    
                     let v = document.querySelector('video');
-                    if (v && isMainElement(v) && v.currentSrc && !v.currentSrc.startsWith('blob:')) {
-                        candidates.push("DOM_VIDEO|" + v.currentSrc);
+                    let mainVideo = !!(v && isMainElement(v));
+                    if (mainVideo && v.currentSrc && !v.currentSrc.startsWith('blob:')) {
+                        let edge = Math.min(v.videoWidth, v.videoHeight);
+                        candidates.push("DOM_VIDEO|" + v.currentSrc + "|" + (edge || ""));
                     }
 
                     
                     let images = Array.from(document.querySelectorAll('img'));
                     let target = images.find(i => isMainElement(i) && !i.src.includes('150x150') && !i.alt.includes('profile'));
                     if (target) {
-                         if (target.srcset) {
-                             let parts = target.srcset.split(',');
-                             candidates.push("DOM_IMAGE|" + parts[parts.length - 1].trim().split(' ')[0]);
+                         for (const part of (target.srcset || '').split(',')) {
+                             let [src, descriptor] = part.trim().split(/\s+/);
+                             if (!src) continue;
+                             let width = descriptor && descriptor.endsWith('w') ? parseInt(descriptor) : NaN;
+                             candidates.push("DOM_IMAGE|" + src + "|" + (width || ""));
                          }
-                         candidates.push("DOM_IMAGE|" + target.src);
+                         let edge = target.currentSrc === target.src ? Math.min(target.naturalWidth, target.naturalHeight) : 0;
+                         candidates.push("DOM_IMAGE|" + target.src + "|" + (edge || ""));
                     }
 
-                    return [...new Set(candidates)].join(';');
+                    return JSON.stringify({ video: mainVideo, candidates: [...new Set(candidates)] });
                 })()
             "#;
 
             let raw_result = match self.tab.evaluate(js_identify, false) {
-                Ok(res) => res.value.unwrap().as_str().unwrap_or("").to_string(),
-                Err(_) => "".to_string(),
+                Ok(res) => res.value.and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default(),
+                Err(_) => String::new(),
             };
+            let identified: serde_json::Value = serde_json::from_str(&raw_result).unwrap_or_default();
+            let video_on_screen = identified["video"].as_bool().unwrap_or(false);
+            let found = identified["candidates"].as_array().cloned().unwrap_or_default();
 
-            let items: Vec<&str> = raw_result.split(';').collect();
-            let mut found_new = false;
-
-            for item in items {
-                if item.is_empty() { continue; }
-                let parts: Vec<&str> = item.split('|').collect();
-                if parts.len() < 2 { continue; }
-                
-                let source_type = parts[0]; 
-                let mut url = parts[1].to_string();
+            let requests = self.network.media();
+            let network = requests.iter().map(|r| ("NET", r.url.as_str(), None, r.mime.as_deref(), r.size.filter(|s| *s > 0 && !r.from_cache)));
+            let page = found.iter().filter_map(|item| {
+                let mut fields = item.as_str()?.splitn(3, '|');
+                let (source, url) = (fields.next()?, fields.next()?);
+                Some((source, url, fields.next().and_then(|w| w.parse().ok()), None, None))
+            });
 
+            let mut candidates: Vec<Candidate> = Vec::new();
+            for (source, url, hint, mime, bytes) in network.chain(page) {
                 if url.len() < 15 { continue; }
-
-                if url.contains(".mp4") {
-                    if let Some(idx) = url.find("&bytestart") { url = url[..idx].to_string(); }
-                    if let Some(idx) = url.find("?bytestart") { url = url[..idx].to_string(); }
+                let url = strip_byte_range(url);
+                let quality = quality::estimate(&url, hint);
+                match candidates.iter_mut().find(|c| c.url == url) {
+                    Some(existing) => {
+                        existing.quality = existing.quality.max(quality);
+                        existing.bytes = existing.bytes.max(bytes);
+                        if existing.mime.is_none() { existing.mime = mime.map(str::to_string); }
+                    },
+                    None => candidates.push(Candidate {
+                        source,
+                        url_id: media_id_from_url(&url),
+                        mime: mime.map(str::to_string),
+                        bytes,
+                        quality,
+                        url,
+                        shown: false,
+                    }),
                 }
+            }
+            rank_candidates(&mut candidates, meta.media_id.as_deref(), video_on_screen);
+            let ranking: Vec<String> = candidates.iter().map(Candidate::describe).collect();
+            debug!("Ranked {} candidates: {}", ranking.len(), ranking.join(", "));
+            let mut streams: Vec<&Candidate> = candidates.iter().filter(|c| c.ext() == "mp4").collect();
+            streams.sort_by_key(|c| !c.is_audio());
+            let video_urls: Vec<String> = streams.into_iter().map(|c| c.url.clone()).collect();
+            // The slide's own media decides the filter; other candidates may be prefetched or leftover streams.
+            let on_slide: Vec<&str> = if video_on_screen {
+                vec!["mp4"]
+            } else {
+                candidates.iter().filter(|c| c.shown).map(Candidate::ext).collect()
+            };
+            if !on_slide.is_empty() && !on_slide.iter().any(|ext| target.media.allows(ext)) {
+                return Ok(SlideOutcome::Filtered);
+            }
+            let mut found_new = false;
+            let mut filtered = false;
+            let mut wanted = false;
+
+            for (rank, candidate) in candidates.into_iter().enumerate() {
+                let ext = candidate.ext();
+                let Candidate { source: source_type, url, url_id, quality, shown, .. } = candidate;
+                if !target.media.allows(ext) { filtered = true; continue; }
+                wanted = true;
 
-                
-                if history.contains(&url) { continue; }
+                // Lower-ranked candidates are usually other sizes of the same story, so stop here.
+                // Off-screen ones may be leftovers of the previous slide, so only skip those.
+                if self.is_archived(url_id.as_deref()) {
+                    debug!("Candidate {} already archived.", url);
+                    if shown { return Ok(SlideOutcome::Archived); }
+                    continue;
+                }
                
                 if failed_urls_this_slide.contains(&url) { continue; }
 
-                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-                let ext = if url.contains(".mp4") { "mp4" } else { "jpg" };
-                let fname = format!("{}_{}.{}", username, timestamp, ext);
-
-                log_info(&format!("Found {} via {}! Downloading...", ext, source_type));
-
-                let js_fetch = format!(r#"
-                    (async function() {{
-                        try {{
-                            const response = await fetch("{}", {{ cache: 'force-cache' }});
-                            const blob = await response.blob();
-                            return await new Promise((resolve) => {{
-                                const reader = new FileReader();
-                                reader.onloadend = () => resolve(reader.result);
-                                reader.readAsDataURL(blob);
-                            }});
-                        }} catch (err) {{ return "ERROR"; }}
-                    }})()
-                "#, url);
-
-                match self.tab.evaluate(&js_fetch, true) {
-                    Ok(res_fetch) => {
-                        if let Some(data_val) = res_fetch.value {
-                            let data_uri = data_val.as_str().unwrap_or("");
-                            if data_uri.starts_with("data:") {
-                                if let Ok(_) = save_base64_file(data_uri, &fname) {
-                                    history.insert(url);
-                                    found_new = true;
-                                    break; 
-                                } else {
-                                    
-                                    failed_urls_this_slide.insert(url);
-                                }
-                            } else {
-
-                                failed_urls_this_slide.insert(url);
-                            }
-                        }
+                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                let item = StoryMeta {
+                    media_id: meta.media_id.clone().or(url_id),
+                    ..meta.clone()
+                };
+
+                info!("Found {} ({}) via {}! Downloading...", ext, quality, source_type);
+
+                let temp = match self.fetch_media(downloader, &url).await {
+                    Ok(temp) => temp,
+                    Err(e) => {
+                        debug!("Download of {} failed: {}", url, e);
+                        failed_urls_this_slide.insert(url);
+                        continue;
+                    }
+                };
+                if self.is_archived_content(&temp.sha256) {
+                    debug!("Content of {} already archived.", url);
+                    if shown { return Ok(SlideOutcome::Archived); }
+                    failed_urls_this_slide.insert(url);
+                    continue;
+                }
+                let inspected = fs::File::open(&temp.path)
+                    .map_err(media::Rejection::from)
+                    .and_then(|mut file| media::inspect(&mut file));
+                let info = match inspected {
+                    Ok(info) => info,
+                    Err(reason) => {
+                        warn!("Rejected {} from {}: {}", ext, source_type, reason);
+                        if !reason.is_retryable() { failed_urls_this_slide.insert(url); }
+                        continue;
+                    }
+                };
+                let tracks = if info.kind.is_video() { mp4::track_kinds(&temp.path).ok() } else { None };
+                let (temp, info) = match tracks {
+                    Some(tracks) if !tracks.contains(&TrackKind::Video) => {
+                        debug!("{} is an audio-only stream", url);
+                        failed_urls_this_slide.insert(url.clone());
+                        audio_tracks.push(AudioTrack { url, temp, info });
+                        continue;
+                    },
+                    Some(tracks) if !tracks.contains(&TrackKind::Audio) => {
+                        let others: Vec<&String> = video_urls.iter().filter(|u| **u != url && !failed_urls_this_slide.contains(*u)).collect();
+                        self.add_audio_track(downloader, &url, temp, info, &mut audio_tracks, &others).await
                     },
-                    Err(_) => { failed_urls_this_slide.insert(url); }
+                    _ => (temp, info),
+                };
+                let kind = info.kind;
+                if !kind.is_video() && info.width.unwrap_or(0).max(info.height.unwrap_or(0)) < MIN_IMAGE_EDGE {
+                    debug!("Skipping preview-sized image from {}", source_type);
+                    failed_urls_this_slide.insert(url);
+                    continue;
+                }
+                if !target.media.allows(kind.extension()) {
+                    debug!("Skipping {} ({} excluded for this target)", url, kind.mime());
+                    failed_urls_this_slide.insert(url);
+                    continue;
+                }
+                let media_id = item.media_id.clone().unwrap_or_else(|| temp.sha256[..12].to_string());
+                let fname = file_name(target, &TemplateVars {
+                    username,
+                    taken_at: item.taken_at.unwrap_or(timestamp),
+                    media_id: &media_id,
+                    ext: kind.extension(),
+                    source: source_type,
+                });
+                match persist_media(temp, info, &fname) {
+                    Ok(saved) => {
+                        self.record_download(username, &url, source_type, &item, timestamp, &saved);
+                        let skipped = &ranking[rank + 1..];
+                        if !skipped.is_empty() { info!("Skipped {} lower-ranked candidates: {}", skipped.len(), skipped.join(", ")); }
+                        found_new = true;
+                        break;
+                    },
+                    Err(e) => {
+                        warn!("Failed to save {}: {}", fname, e);
+                        failed_urls_this_slide.insert(url);
+                    }
                 }
             }
 
-            if found_new { return Ok(true); }
+            if found_new { return Ok(SlideOutcome::Saved); }
+            if filtered && !wanted { return Ok(SlideOutcome::Filtered); }
             thread::sleep(Duration::from_millis(500));
         }
-        Ok(false)
+        Ok(SlideOutcome::NotFound)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(source: &'static str, url: &str, id: Option<&str>, mime: Option<&str>, size: u32) -> Candidate<'static> {
+        Candidate {
+            source,
+            url: url.to_string(),
+            url_id: id.map(str::to_string),
+            mime: mime.map(str::to_string),
+            bytes: None,
+            quality: Quality { size: Some(size), bitrate: None },
+            shown: false,
+        }
+    }
+
+    #[test]
+    fn ranks_candidates() {
+        let cases = [
+            (
+                "on-screen media before larger leftovers",
+                vec![
+                    candidate("NET", "https://cdn/prev.jpg", Some("111"), Some("image/jpeg"), 1440),
+                    candidate("DOM_IMAGE", "https://cdn/now.jpg", Some("222"), None, 640),
+                    candidate("NET", "https://cdn/now_big.jpg", Some("222"), Some("image/jpeg"), 1080),
+                ],
+                Some("222"),
+                false,
+                vec!["https://cdn/now_big.jpg", "https://cdn/now.jpg", "https://cdn/prev.jpg"],
+            ),
+            (
+                "video over poster on a blob: video slide",
+                vec![
+                    candidate("DOM_IMAGE", "https://cdn/poster.jpg", Some("333"), None, 1080),
+                    candidate("NET", "https://cdn/stream_720.mp4", None, Some("video/mp4"), 720),
+                    candidate("NET", "https://cdn/stream_480.mp4", None, Some("video/mp4"), 480),
+                ],
+                None,
+                true,
+                vec!["https://cdn/stream_720.mp4", "https://cdn/stream_480.mp4", "https://cdn/poster.jpg"],
+            ),
+            (
+                "image before stray streams on an image slide",
+                vec![
+                    candidate("NET", "https://cdn/next.mp4", None, Some("video/mp4"), 1080),
+                    candidate("DOM_IMAGE", "https://cdn/photo.jpg", None, None, 640),
+                ],
+                None,
+                false,
+                vec!["https://cdn/photo.jpg", "https://cdn/next.mp4"],
+            ),
+            (
+                "audio after video",
+                vec![
+                    candidate("NET", "https://cdn/audio.mp4", Some("444"), Some("audio/mp4"), 1080),
+                    candidate("NET", "https://cdn/video.mp4", Some("444"), Some("video/mp4"), 720),
+                ],
+                Some("444"),
+                true,
+                vec!["https://cdn/video.mp4", "https://cdn/audio.mp4"],
+            ),
+        ];
+        for (name, mut candidates, slide_id, video_on_screen, expected) in cases {
+            rank_candidates(&mut candidates, slide_id, video_on_screen);
+            let order: Vec<&str> = candidates.iter().map(|c| c.url.as_str()).collect();
+            assert_eq!(order, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn marks_only_slide_media_as_shown() {
+        let mut candidates = vec![
+            candidate("NET", "https://cdn/prev.mp4", Some("111"), Some("video/mp4"), 1080),
+            candidate("NET", "https://cdn/now.mp4", Some("222"), Some("video/mp4"), 720),
+        ];
+        rank_candidates(&mut candidates, Some("222"), true);
+        let shown: Vec<(&str, bool)> = candidates.iter().map(|c| (c.url.as_str(), c.shown)).collect();
+        assert_eq!(shown, [("https://cdn/now.mp4", true), ("https://cdn/prev.mp4", false)]);
     }
 }
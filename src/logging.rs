@@ -0,0 +1,114 @@
+use anyhow::{Result, anyhow};
+use colored::*;
+use std::fmt;
+use std::fs;
+use tracing::{Event, Level, Subscriber};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::format::{FormatEvent, FormatFields, Writer};
+use tracing_subscriber::fmt::writer::MakeWriterExt;
+use tracing_subscriber::fmt::FmtContext;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+use crate::config::LoggingConfig;
+
+pub struct LogOptions<'a> {
+    pub level: &'a str,
+    pub format: &'a str,
+    pub dir: &'a str,
+}
+
+struct ConsoleFormat;
+
+impl<S, N> FormatEvent<S, N> for ConsoleFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let tag = match *event.metadata().level() {
+            Level::ERROR => "[ERROR]".red().bold(),
+            Level::WARN => "[WARN]".yellow().bold(),
+            Level::INFO => "[INFO]".green().bold(),
+            Level::DEBUG => "[DEBUG]".blue().bold(),
+            Level::TRACE => "[TRACE]".dimmed(),
+        };
+        write!(writer, "{} ", tag)?;
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+fn build_filter(level: &str, extra: &str) -> Result<EnvFilter> {
+    let mut directives = format!("warn,stov={}", level);
+    if let Ok(env) = std::env::var("RUST_LOG") {
+        if !env.trim().is_empty() { directives = format!("{},{}", directives, env); }
+    }
+    if !extra.trim().is_empty() {
+        EnvFilter::builder().parse(extra).map_err(|e| anyhow!("`logging.filter` is invalid: {}", e))?;
+        directives = format!("{},{}", directives, extra);
+    }
+
+    EnvFilter::builder()
+        .parse(&directives)
+        .map_err(|e| anyhow!("Invalid log filter {:?}: {}", directives, e))
+}
+
+pub fn init(cfg: &LoggingConfig, opts: LogOptions) -> Result<()> {
+    let filter = build_filter(opts.level, &cfg.filter)?;
+    let json = opts.format == "json";
+
+    // JSON lines go to stderr so they never interleave with the banner and tables on stdout.
+    let console = if json {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_writer(std::io::stderr)
+            .boxed()
+    } else {
+        tracing_subscriber::fmt::layer()
+            .event_format(ConsoleFormat)
+            .with_writer(std::io::stderr.with_max_level(Level::WARN).or_else(std::io::stdout))
+            .boxed()
+    };
+
+    let file = if opts.dir.is_empty() {
+        None
+    } else {
+        fs::create_dir_all(opts.dir).map_err(|e| anyhow!("Failed to create log directory {:?}: {}", opts.dir, e))?;
+        let rotation = match cfg.rotation.as_str() {
+            "hourly" => Rotation::HOURLY,
+            "never" => Rotation::NEVER,
+            _ => Rotation::DAILY,
+        };
+        let appender = RollingFileAppender::builder()
+            .rotation(rotation)
+            .filename_prefix("stov")
+            .filename_suffix(if json { "jsonl" } else { "log" })
+            .max_log_files(cfg.max_files)
+            .build(opts.dir)
+            .map_err(|e| anyhow!("Failed to open log directory {:?}: {}", opts.dir, e))?;
+
+        let layer = if json {
+            tracing_subscriber::fmt::layer()
+                .json()
+                .flatten_event(true)
+                .with_writer(appender)
+                .boxed()
+        } else {
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(appender)
+                .boxed()
+        };
+        Some(layer)
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(console)
+        .with(file)
+        .try_init()
+        .map_err(|e| anyhow!("Failed to initialise logging: {}", e))
+}
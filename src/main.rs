@@ -1,121 +1,470 @@
+mod archive;
 mod config;
+mod credentials;
+mod download;
 mod browser;
+mod cli;
 mod instagram;
+mod logging;
+mod manifest;
+mod media;
+mod mp4;
+mod network;
+mod overlay;
+mod profile;
+mod quality;
+mod sidecar;
+mod targets;
+mod template;
 mod utils;
 
 use std::io::{self, Write};
-use utils::{setup_env, log_info, log_error, clear_terminal, list_profiles, load_profile_session};
-use instagram::InstagramBot;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use clap::Parser;
+use headless_chrome::Browser;
+use tracing::{info, warn, error};
+use utils::{setup_env, clear_terminal};
+use profile::{list_profiles, load_profile, read_profile, save_profile, encrypt_profile, record_verification, profile_summaries, delete_profile, rename_profile, export_profile, import_profile, UserProfile};
+use archive::Archive;
+use manifest::Problem;
+use instagram::{InstagramBot, SessionStatus};
 use browser::launch_browser;
-use colored::*; 
+use cli::{Cli, Command, LoginArgs, ProfileCommand, RunArgs, WatchArgs};
+use targets::{Target, load_targets_file};
+use colored::*;
+use credentials::{CredentialSource, prompt_hidden};
+use chrono::{Local, TimeZone};
+use rand::Rng;
+use tokio::sync::Notify;
+
+struct Schedule {
+    interval: Duration,
+    jitter: Duration,
+}
+
+enum Session {
+    Saved(UserProfile),
+    Credentials { username: String, password: String },
+}
+
+impl Session {
+    fn username(&self) -> &str {
+        match self {
+            Session::Saved(profile) => &profile.username,
+            Session::Credentials { username, .. } => username,
+        }
+    }
+}
 
 #[tokio::main]
-async fn main() {
-    println!("Build Complete. Press {} to launch STOV...", "ENTER".yellow().bold());
-    let _ = io::stdin().read_line(&mut String::new());
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let config_path = match config::load(cli.config.as_deref()) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("{} {}", "[ERROR]".red().bold(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let log_cfg = &config::get().logging;
+    let log_opts = logging::LogOptions {
+        level: cli.log_level.as_deref().unwrap_or(&log_cfg.level),
+        format: cli.log_format.as_deref().unwrap_or(&log_cfg.format),
+        dir: cli.log_dir.as_deref().unwrap_or(&log_cfg.dir),
+    };
+    if let Err(e) = logging::init(log_cfg, log_opts) {
+        eprintln!("{} {}", "[ERROR]".red().bold(), e);
+        return ExitCode::FAILURE;
+    }
+    if let Some(path) = config_path {
+        info!("Loaded config from {:?}", path);
+    }
+
+    if cli.command.is_none() {
+        println!("Build Complete. Press {} to launch STOV...", "ENTER".yellow().bold());
+        let _ = io::stdin().read_line(&mut String::new());
+        clear_terminal();
+    }
 
-    clear_terminal();
     setup_env();
 
+    let result = match cli.command {
+        None => run(RunArgs::default()).await.map(|_| ExitCode::SUCCESS),
+        Some(Command::Run(args)) => run(args).await.map(|_| ExitCode::SUCCESS),
+        Some(Command::Watch(args)) => watch(args).await.map(|_| ExitCode::SUCCESS),
+        Some(Command::Login(args)) => login(args).map(|_| ExitCode::SUCCESS),
+        Some(Command::Check { names }) => check(names),
+        Some(Command::Verify { targets }) => verify(targets),
+        Some(Command::Profiles { action }) => manage_profiles(action.unwrap_or(ProfileCommand::List)).map(|_| ExitCode::SUCCESS),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn print_banner() {
     println!("{}", "======================================".cyan().bold());
     println!("{}", "       STOV - TERMUX EDITION          ".cyan().bold());
     println!("{}", "   State of the Art Observation Tool  ".white().italic());
     println!("{}", "======================================".cyan().bold());
-    println!("");
+    println!();
+}
 
-    let mut username = String::new();
-    let mut password = String::new();
-    let mut use_saved_session = false;
-    let mut saved_session_id = String::new();
+fn prompt(label: &str) -> String {
+    print!("{} ", label);
+    io::stdout().flush().unwrap();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
+}
 
+fn choose_saved_profile() -> Option<UserProfile> {
     let profiles = list_profiles().unwrap_or_default();
-    
-    if !profiles.is_empty() {
-        println!("Saved Profiles Found:");
-        println!("1. Login with New Account");
-        println!("2. Use Saved Account");
-        print!("\nSelect Option (1/2): ");
-        io::stdout().flush().unwrap();
-        
-        let mut choice = String::new();
-        io::stdin().read_line(&mut choice).unwrap();
-        
-        if choice.trim() == "2" {
-            println!("\nSelect Profile:");
-            for (i, prof) in profiles.iter().enumerate() {
-                println!("{}. {}", i + 1, prof);
+    if profiles.is_empty() { return None; }
+
+    println!("Saved Profiles Found:");
+    println!("1. Login with New Account");
+    println!("2. Use Saved Account");
+    if prompt("\nSelect Option (1/2):") != "2" { return None; }
+
+    println!("\nSelect Profile:");
+    for (i, prof) in profiles.iter().enumerate() {
+        println!("{}. {}", i + 1, prof);
+    }
+
+    let idx = prompt("Enter Number:").parse::<usize>().ok()?;
+    if idx == 0 || idx > profiles.len() { return None; }
+
+    let selected_user = &profiles[idx - 1];
+    match load_profile(selected_user) {
+        Ok(profile) => {
+            info!("Loaded session for {}", selected_user);
+            Some(profile)
+        },
+        Err(_) => {
+            warn!("Failed to load session. Switching to manual login.");
+            None
+        }
+    }
+}
+
+fn read_credentials(args: LoginArgs) -> Result<(String, String)> {
+    let username = args.username.unwrap_or_else(|| prompt(&"Your Username:".yellow().to_string()));
+    if username.trim().is_empty() { return Err(anyhow!("No username given (use --username when stdin is not a terminal).")); }
+
+    if let Some(password) = args.password {
+        return Ok((username, password));
+    }
+
+    let source = if let Some(var) = args.password_env {
+        CredentialSource::Env { var }
+    } else if let Some(path) = args.password_file {
+        CredentialSource::File { path }
+    } else if let Some(command) = args.password_command {
+        CredentialSource::Command { command }
+    } else {
+        config::get().credentials.get(&username).cloned().unwrap_or(CredentialSource::Prompt)
+    };
+
+    let password = source
+        .read("Your Password:")
+        .map_err(|e| anyhow!("Failed to read password for {}: {}", username, e))?;
+    Ok((username, password))
+}
+
+fn resolve_session(args: &mut RunArgs) -> Result<Session> {
+    if let Some(name) = &args.profile {
+        let profile = load_profile(name).map_err(|e| anyhow!("Failed to load profile '{}': {}", name, e))?;
+        info!("Loaded session for {}", name);
+        return Ok(Session::Saved(profile));
+    }
+
+    if args.login.username.is_none() {
+        if let Some(profile) = choose_saved_profile() { return Ok(Session::Saved(profile)); }
+    }
+
+    let (username, password) = read_credentials(std::mem::take(&mut args.login))?;
+    Ok(Session::Credentials { username, password })
+}
+
+fn resolve_targets(args: &RunArgs) -> Result<Vec<Target>> {
+    let mut names = args.targets.clone();
+    let mut targets = Vec::new();
+
+    if let Some(path) = &args.targets_file {
+        targets = load_targets_file(path)?;
+    }
+
+    if names.is_empty() && targets.is_empty() {
+        let input = prompt(&"Targets (e.g. user1,user2):".yellow().to_string());
+        names = input.split(',').map(|s| s.to_string()).collect();
+    }
+
+    for name in names.iter().map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        targets.push(Target::new(name)?);
+    }
+    if args.highlights {
+        for target in &mut targets { target.highlights = true; }
+    }
+    Ok(targets)
+}
+
+fn refresh_session(bot: &InstagramBot, username: &str) {
+    match bot.export_cookies().and_then(|cookies| save_profile(username, cookies)) {
+        Ok(()) => info!("Session cookies refreshed for {}.", username),
+        Err(e) => warn!("Failed to refresh saved session for {}: {}", username, e),
+    }
+}
+
+fn start_browser() -> Result<Browser> {
+    launch_browser().map_err(|e| {
+        println!("Ensure you ran: pkg install chromium");
+        anyhow!("Browser Launch Failed: {}", e)
+    })
+}
+
+async fn run(args: RunArgs) -> Result<()> {
+    scrape(args, None).await
+}
+
+async fn watch(args: WatchArgs) -> Result<()> {
+    let cfg = &config::get().watch;
+    let interval = config::parse_duration(args.interval.as_deref().unwrap_or(&cfg.interval)).map_err(|e| anyhow!("--interval: {}", e))?;
+    let jitter = config::parse_duration(args.jitter.as_deref().unwrap_or(&cfg.jitter)).map_err(|e| anyhow!("--jitter: {}", e))?;
+    if interval.is_zero() { return Err(anyhow!("Watch interval must be greater than zero.")); }
+
+    scrape(args.run, Some(Schedule { interval, jitter })).await
+}
+
+async fn scrape(mut args: RunArgs, schedule: Option<Schedule>) -> Result<()> {
+    print_banner();
+
+    let session = resolve_session(&mut args)?;
+    let targets = resolve_targets(&args)?;
+    if targets.is_empty() { return Err(anyhow!("No targets given.")); }
+
+    println!();
+
+    let archive = Archive::open(&config::get().archive.database)?;
+
+    let browser = start_browser()?;
+    let bot = InstagramBot::new(&browser)
+        .map_err(|e| anyhow!("Tab Creation Failed: {}", e))?
+        .with_archive(archive);
+
+    let login_result = match &session {
+        Session::Saved(profile) => bot.login_with_session(profile),
+        Session::Credentials { username, password } => bot.login(username, password),
+    };
+    login_result.map_err(|e| anyhow!("Login Critical Error: {}", e))?;
+
+    let Some(schedule) = schedule else {
+        bot.process_targets(&targets).await.map_err(|e| anyhow!("Scraping Error: {}", e))?;
+        refresh_session(&bot, session.username());
+        info!("Operation Completed.");
+        return Ok(());
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let wake = Arc::new(Notify::new());
+    {
+        let stop = stop.clone();
+        let wake = wake.clone();
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if stop.swap(true, Ordering::SeqCst) { std::process::exit(130); }
+                info!("Stopping watch mode (press Ctrl-C again to force quit)...");
+                wake.notify_one();
             }
-            print!("Enter Number: ");
-            io::stdout().flush().unwrap();
-            
-            let mut prof_choice = String::new();
-            io::stdin().read_line(&mut prof_choice).unwrap();
-            
-            if let Ok(idx) = prof_choice.trim().parse::<usize>() {
-                if idx > 0 && idx <= profiles.len() {
-                    let selected_user = &profiles[idx - 1];
-                    if let Ok(sid) = load_profile_session(selected_user) {
-                        log_info(&format!("Loaded session for {}", selected_user));
-                        saved_session_id = sid;
-                        use_saved_session = true;
-                    } else {
-                        log_error("Failed to load session. Switching to manual login.");
-                    }
+        });
+    }
+
+    let mut cycle = 1;
+    while !stop.load(Ordering::SeqCst) {
+        info!("Watch cycle #{} started.", cycle);
+        match bot.process_targets(&targets).await {
+            Ok(()) => refresh_session(&bot, session.username()),
+            Err(e) => error!("Scraping Error: {}", e),
+        }
+        if stop.load(Ordering::SeqCst) { break; }
+
+        let jitter_secs = rand::thread_rng().gen_range(0..=schedule.jitter.as_secs());
+        let wait = schedule.interval.saturating_add(Duration::from_secs(jitter_secs));
+        info!("Cycle #{} complete. Next cycle in {} min.", cycle, wait.as_secs() / 60);
+
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {},
+            _ = wake.notified() => {},
+        }
+        cycle += 1;
+    }
+
+    info!("Watch mode stopped.");
+    Ok(())
+}
+
+fn login(args: LoginArgs) -> Result<()> {
+    print_banner();
+
+    let (username, password) = read_credentials(args)?;
+
+    let browser = start_browser()?;
+    let bot = InstagramBot::new(&browser).map_err(|e| anyhow!("Tab Creation Failed: {}", e))?;
+    bot.login(&username, &password).map_err(|e| anyhow!("Login Critical Error: {}", e))?;
+
+    info!("Operation Completed.");
+    Ok(())
+}
+
+fn check(names: Vec<String>) -> Result<ExitCode> {
+    let names = if names.is_empty() { list_profiles()? } else { names };
+    if names.is_empty() { return Err(anyhow!("No saved profiles to check.")); }
+
+    let browser = start_browser()?;
+    let bot = InstagramBot::new(&browser).map_err(|e| anyhow!("Tab Creation Failed: {}", e))?;
+
+    let mut worst = 0u8;
+    let mut results = Vec::new();
+    for name in names {
+        let status = read_profile(&name).and_then(|profile| {
+            if profile.has_session() { bot.check_session(&profile) } else { Ok(SessionStatus::Expired) }
+        });
+        let (label, code) = match &status {
+            Ok(SessionStatus::Valid) => ("VALID".green(), 0),
+            Ok(SessionStatus::Expired) => ("EXPIRED".yellow(), 2),
+            Ok(SessionStatus::Checkpointed) => ("CHECKPOINTED".red(), 3),
+            Err(_) => ("ERROR".red(), 1),
+        };
+        match &status {
+            Ok(s) => {
+                if let Err(e) = record_verification(&name, s.as_str()) {
+                    warn!("Failed to record verification for {}: {}", name, e);
                 }
-            }
+            },
+            Err(e) => error!("Check failed for {}: {}", name, e),
         }
+        // An unchecked profile outranks expired and checkpointed ones, so monitoring always sees it.
+        if code == 1 || (worst != 1 && code > worst) { worst = code; }
+        results.push((name, label));
+    }
+
+    println!();
+    for (name, label) in results {
+        println!("{:<24} {}", name, label);
     }
+    Ok(ExitCode::from(worst))
+}
 
-    if !use_saved_session {
-        print!("{} ", "Your Username:".yellow());
-        io::stdout().flush().unwrap();
-        io::stdin().read_line(&mut username).unwrap();
+fn verify(targets: Vec<String>) -> Result<ExitCode> {
+    let report = manifest::verify(&targets)?;
 
-        print!("{} ", "Your Password:".yellow());
-        io::stdout().flush().unwrap();
-        io::stdin().read_line(&mut password).unwrap();
+    for (path, problem) in &report.problems {
+        match problem {
+            Problem::Missing => println!("{:<12} {}", "MISSING".red(), path),
+            Problem::Corrupted(reason) => println!("{:<12} {} ({})", "CORRUPTED".red(), path, reason),
+            Problem::Untracked => println!("{:<12} {}", "UNTRACKED".yellow(), path),
+        }
     }
 
-    let mut targets_input = String::new();
-    print!("{} ", "Targets (e.g. user1,user2):".yellow());
-    io::stdout().flush().unwrap();
-    io::stdin().read_line(&mut targets_input).unwrap();
-
-    let targets: Vec<String> = targets_input
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect();
-
-    println!(""); 
-    
-    match launch_browser() {
-        Ok(browser) => {
-            match InstagramBot::new(&browser) {
-                Ok(bot) => {
-                    let login_result = if use_saved_session {
-                        bot.login_with_session(&saved_session_id)
-                    } else {
-                        bot.login(username.trim(), password.trim())
-                    };
-
-                    if let Err(e) = login_result {
-                        log_error(&format!("Login Critical Error: {}", e));
-                        return;
-                    }
-
-                    if let Err(e) = bot.process_targets(targets).await {
-                        log_error(&format!("Scraping Error: {}", e));
-                    }
-                },
-                Err(e) => log_error(&format!("Tab Creation Failed: {}", e)),
+    let missing = report.problems.iter().filter(|(_, p)| matches!(p, Problem::Missing)).count();
+    let corrupted = report.problems.iter().filter(|(_, p)| matches!(p, Problem::Corrupted(_))).count();
+    let untracked = report.problems.len() - missing - corrupted;
+    info!("Verified {} files: {} missing, {} corrupted, {} untracked.", report.checked, missing, corrupted, untracked);
+
+    Ok(if report.problems.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+fn manage_profiles(action: ProfileCommand) -> Result<()> {
+    match action {
+        ProfileCommand::List => show_profiles(),
+        ProfileCommand::Encrypt { names } => encrypt_profiles(names),
+        ProfileCommand::Delete { name, yes } => {
+            if !yes && prompt(&format!("Delete profile {}? (y/N):", name).yellow().to_string()).to_lowercase() != "y" {
+                info!("Aborted.");
+                return Ok(());
             }
+            delete_profile(&name)?;
+            info!("Profile {} deleted.", name);
+            Ok(())
         },
-        Err(e) => {
-            log_error(&format!("Browser Launch Failed: {}", e));
-            println!("Ensure you ran: pkg install chromium");
+        ProfileCommand::Rename { old, new } => {
+            rename_profile(&old, &new)?;
+            info!("Profile {} renamed to {}.", old, new);
+            Ok(())
+        },
+        ProfileCommand::Export { name, file, passphrase_env } => {
+            let passphrase = export_passphrase(passphrase_env, true)?;
+            export_profile(&name, &file, &passphrase)?;
+            info!("Profile {} exported to {:?}.", name, file);
+            Ok(())
+        },
+        ProfileCommand::Import { file, name, force, passphrase_env } => {
+            let passphrase = export_passphrase(passphrase_env, false)?;
+            let name = import_profile(&file, &passphrase, name.as_deref(), force)?;
+            info!("Profile {} imported from {:?}.", name, file);
+            Ok(())
+        },
+    }
+}
+
+fn export_passphrase(env_var: Option<String>, confirm: bool) -> Result<String> {
+    if let Some(var) = env_var {
+        return CredentialSource::Env { var }.read("");
+    }
+    let passphrase = prompt_hidden("Export Passphrase:")?;
+    if passphrase.is_empty() { return Err(anyhow!("Export passphrase must not be empty.")); }
+    if confirm && prompt_hidden("Repeat Passphrase:")? != passphrase {
+        return Err(anyhow!("Passphrases do not match."));
+    }
+    Ok(passphrase)
+}
+
+fn format_timestamp(ts: Option<u64>) -> String {
+    ts.and_then(|t| Local.timestamp_opt(t as i64, 0).single())
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "never".to_string())
+}
+
+fn show_profiles() -> Result<()> {
+    let profiles = profile_summaries()?;
+    if profiles.is_empty() {
+        info!("No saved profiles.");
+        return Ok(());
+    }
+
+    println!("{:<24} {:<10} {:<17} {:<17} {}", "PROFILE".bold(), "STORAGE".bold(), "LAST USED".bold(), "LAST VERIFIED".bold(), "STATUS".bold());
+    for summary in profiles {
+        match summary {
+            Ok(p) => {
+                let storage = if p.encrypted { "encrypted".green() } else { "plaintext".yellow() };
+                let status = p.meta.last_status.as_deref().unwrap_or("-");
+                println!("{:<24} {:<10} {:<17} {:<17} {}", p.name, storage, format_timestamp(p.meta.last_used), format_timestamp(p.meta.last_verified), status);
+            },
+            Err(e) => println!("{}", format!("{} (unreadable)", e).red()),
+        }
+    }
+    Ok(())
+}
+
+fn encrypt_profiles(names: Vec<String>) -> Result<()> {
+    let names = if names.is_empty() { list_profiles()? } else { names };
+    for name in names {
+        match encrypt_profile(&name) {
+            Ok(true) => info!("Profile {} encrypted.", name),
+            Ok(false) => info!("Profile {} is already encrypted.", name),
+            Err(e) => return Err(anyhow!("Failed to encrypt profile {}: {}", name, e)),
         }
     }
-    
-    log_info("Operation Completed.");
+    Ok(())
 }
@@ -0,0 +1,149 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::config;
+use crate::utils::{SavedMedia, sha256_file, write_atomic};
+
+const MANIFEST_DIR: &str = ".manifests";
+
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub target: String,
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub sha256: String,
+    pub size: u64,
+    pub saved_at: u64,
+}
+
+pub enum Problem {
+    Missing,
+    Corrupted(String),
+    Untracked,
+}
+
+pub struct VerifyReport {
+    pub checked: usize,
+    pub problems: Vec<(String, Problem)>,
+}
+
+fn manifest_dir() -> PathBuf {
+    Path::new(&config::get().paths.download_dir).join(MANIFEST_DIR)
+}
+
+fn manifest_path(target: &str) -> PathBuf {
+    manifest_dir().join(format!("{}.json", target))
+}
+
+fn load(target: &str) -> Result<Manifest> {
+    let path = manifest_path(target);
+    if !path.exists() {
+        return Ok(Manifest { target: target.to_string(), files: BTreeMap::new() });
+    }
+    let data = fs::read_to_string(&path)?;
+    serde_json::from_str(&data).map_err(|e| anyhow!("Manifest {:?} is corrupted: {}", path, e))
+}
+
+fn save(manifest: &Manifest) -> Result<()> {
+    fs::create_dir_all(manifest_dir())?;
+    let json = serde_json::to_string_pretty(manifest)?;
+    write_atomic(&manifest_path(&manifest.target), json.as_bytes())
+}
+
+fn relative_path(path: &str) -> String {
+    let root = Path::new(&config::get().paths.download_dir);
+    Path::new(path)
+        .strip_prefix(root)
+        .unwrap_or(Path::new(path))
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub fn record(target: &str, saved: &SavedMedia) -> Result<()> {
+    let mut manifest = load(target)?;
+    let saved_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    manifest.files.insert(relative_path(&saved.path), ManifestEntry {
+        sha256: saved.sha256.clone(),
+        size: saved.size,
+        saved_at,
+    });
+    save(&manifest)
+}
+
+pub fn list_targets() -> Result<Vec<String>> {
+    let dir = manifest_dir();
+    if !dir.exists() { return Ok(Vec::new()); }
+    let mut targets = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") { continue; }
+        if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) { targets.push(stem.to_string()); }
+    }
+    targets.sort();
+    Ok(targets)
+}
+
+fn walk(dir: &Path, root: &Path, out: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') { continue; }
+        if entry.file_type()?.is_dir() {
+            walk(&path, root, out)?;
+        } else if let Ok(rel) = path.strip_prefix(root) {
+            out.push(rel.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+pub fn verify(targets: &[String]) -> Result<VerifyReport> {
+    let root = PathBuf::from(&config::get().paths.download_dir);
+    let scan_untracked = targets.is_empty();
+    let targets = if scan_untracked { list_targets()? } else { targets.to_vec() };
+    if let Some(target) = targets.iter().find(|t| !manifest_path(t).exists()) {
+        return Err(anyhow!("No manifest for target {} (nothing has been saved for it yet)", target));
+    }
+
+    let mut report = VerifyReport { checked: 0, problems: Vec::new() };
+    let mut tracked = HashSet::new();
+
+    for target in &targets {
+        let manifest = load(target)?;
+        for (rel, entry) in &manifest.files {
+            tracked.insert(rel.clone());
+            report.checked += 1;
+            let path = root.join(rel);
+            if !path.exists() {
+                report.problems.push((rel.clone(), Problem::Missing));
+                continue;
+            }
+            let size = fs::metadata(&path)?.len();
+            if size != entry.size {
+                report.problems.push((rel.clone(), Problem::Corrupted(format!("size {} != {}", size, entry.size))));
+                continue;
+            }
+            if sha256_file(&path)? != entry.sha256 {
+                report.problems.push((rel.clone(), Problem::Corrupted("sha256 mismatch".to_string())));
+            }
+        }
+    }
+
+    if scan_untracked && root.exists() {
+        let mut files = Vec::new();
+        walk(&root, &root, &mut files)?;
+        files.sort();
+        for rel in files {
+            if tracked.contains(&rel) { continue; }
+            let is_sidecar = rel.strip_suffix(".json").is_some_and(|media| tracked.contains(media) || root.join(media).exists());
+            if !is_sidecar { report.problems.push((rel, Problem::Untracked)); }
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,429 @@
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MediaKind {
+    Jpeg,
+    Png,
+    Webp,
+    Heic,
+    Mp4,
+}
+
+const HEIC_BRANDS: &[&[u8]] = &[b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1"];
+
+impl MediaKind {
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) { return Some(MediaKind::Jpeg); }
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") { return Some(MediaKind::Png); }
+        if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" { return Some(MediaKind::Webp); }
+        if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" {
+            let brand = &bytes[8..12];
+            if HEIC_BRANDS.contains(&brand) { return Some(MediaKind::Heic); }
+            return Some(MediaKind::Mp4);
+        }
+        None
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MediaKind::Jpeg => "jpg",
+            MediaKind::Png => "png",
+            MediaKind::Webp => "webp",
+            MediaKind::Heic => "heic",
+            MediaKind::Mp4 => "mp4",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            MediaKind::Jpeg => "image/jpeg",
+            MediaKind::Png => "image/png",
+            MediaKind::Webp => "image/webp",
+            MediaKind::Heic => "image/heic",
+            MediaKind::Mp4 => "video/mp4",
+        }
+    }
+
+    pub fn is_video(&self) -> bool {
+        matches!(self, MediaKind::Mp4)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MediaInfo {
+    pub kind: MediaKind,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub duration: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum Rejection {
+    /// Not a supported media file at all (error page, placeholder, unknown codec).
+    Unrecognised,
+    /// The file ends early; fetching it again may return the complete file.
+    Truncated(String),
+    /// The file is complete but structurally invalid; retrying will not help.
+    Malformed(String),
+}
+
+impl Rejection {
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Rejection::Truncated(_))
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Unrecognised => write!(f, "unrecognised media format"),
+            Rejection::Truncated(reason) => write!(f, "truncated: {}", reason),
+            Rejection::Malformed(reason) => write!(f, "malformed: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+impl From<io::Error> for Rejection {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            Rejection::Truncated("unexpected end of file".to_string())
+        } else {
+            Rejection::Malformed(e.to_string())
+        }
+    }
+}
+
+pub fn inspect<R: Read + Seek>(r: &mut R) -> Result<MediaInfo, Rejection> {
+    let len = r.seek(SeekFrom::End(0))?;
+    r.seek(SeekFrom::Start(0))?;
+    let mut head = [0u8; 16];
+    let n = r.take(16).read(&mut head)?;
+    let kind = MediaKind::sniff(&head[..n]).ok_or(Rejection::Unrecognised)?;
+
+    let mut info = MediaInfo { kind, width: None, height: None, duration: None };
+    match kind {
+        MediaKind::Jpeg => validate_jpeg(r, len, &mut info)?,
+        MediaKind::Png => validate_png(r, len, &mut info)?,
+        MediaKind::Webp => validate_webp(r, len, &mut info)?,
+        MediaKind::Heic => validate_heic(r, len, &mut info)?,
+        MediaKind::Mp4 => validate_mp4(r, len, &mut info)?,
+    }
+    Ok(info)
+}
+
+fn read_array<R: Read, const N: usize>(r: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u16<R: Read>(r: &mut R) -> io::Result<u16> { Ok(u16::from_be_bytes(read_array(r)?)) }
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> { Ok(u32::from_be_bytes(read_array(r)?)) }
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> { Ok(u64::from_be_bytes(read_array(r)?)) }
+
+fn validate_jpeg<R: Read + Seek>(r: &mut R, len: u64, info: &mut MediaInfo) -> Result<(), Rejection> {
+    r.seek(SeekFrom::Start(2))?;
+    loop {
+        let mut marker = read_array::<_, 2>(r)?;
+        if marker[0] != 0xFF {
+            return Err(Rejection::Malformed(format!("expected JPEG marker at offset {}", r.stream_position()? - 2)));
+        }
+        while marker[1] == 0xFF { marker[1] = read_array::<_, 1>(r)?[0]; }
+        match marker[1] {
+            0x01 | 0xD0..=0xD7 => continue,
+            0xD9 => return Err(Rejection::Malformed("end of image before scan data".to_string())),
+            0xDA => break,
+            _ => {}
+        }
+        let seg_len = read_u16(r)? as i64;
+        if seg_len < 2 { return Err(Rejection::Malformed("invalid JPEG segment length".to_string())); }
+        if matches!(marker[1], 0xC0..=0xCF) && !matches!(marker[1], 0xC4 | 0xC8 | 0xCC) {
+            if seg_len < 7 { return Err(Rejection::Malformed("invalid JPEG frame header length".to_string())); }
+            let _precision = read_array::<_, 1>(r)?;
+            info.height = Some(read_u16(r)? as u32);
+            info.width = Some(read_u16(r)? as u32);
+            r.seek(SeekFrom::Current(seg_len - 7))?;
+        } else {
+            r.seek(SeekFrom::Current(seg_len - 2))?;
+        }
+        if r.stream_position()? > len {
+            return Err(Rejection::Truncated("JPEG header runs past end of file".to_string()));
+        }
+    }
+    if info.width.is_none() { return Err(Rejection::Malformed("no JPEG frame header".to_string())); }
+
+    let tail_len = len.min(4096);
+    r.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = vec![0u8; tail_len as usize];
+    r.read_exact(&mut tail)?;
+    let end = tail.iter().rposition(|&b| b != 0).map(|i| i + 1).unwrap_or(0);
+    if !tail[..end].ends_with(&[0xFF, 0xD9]) {
+        return Err(Rejection::Truncated("missing JPEG end-of-image marker".to_string()));
+    }
+    Ok(())
+}
+
+fn validate_png<R: Read + Seek>(r: &mut R, len: u64, info: &mut MediaInfo) -> Result<(), Rejection> {
+    r.seek(SeekFrom::Start(8))?;
+    let ihdr_len = read_u32(r)?;
+    if ihdr_len != 13 || &read_array::<_, 4>(r)? != b"IHDR" {
+        return Err(Rejection::Malformed("PNG does not start with an IHDR chunk".to_string()));
+    }
+    info.width = Some(read_u32(r)?);
+    info.height = Some(read_u32(r)?);
+
+    if len < 12 + 33 { return Err(Rejection::Truncated("PNG has no image data".to_string())); }
+    r.seek(SeekFrom::Start(len - 12))?;
+    let iend = read_array::<_, 12>(r)?;
+    if iend != *b"\0\0\0\0IEND\xAE\x42\x60\x82" {
+        return Err(Rejection::Truncated("missing PNG IEND chunk".to_string()));
+    }
+    Ok(())
+}
+
+fn validate_webp<R: Read + Seek>(r: &mut R, len: u64, info: &mut MediaInfo) -> Result<(), Rejection> {
+    r.seek(SeekFrom::Start(4))?;
+    let riff_size = u32::from_le_bytes(read_array(r)?) as u64;
+    if riff_size + 8 > len {
+        return Err(Rejection::Truncated(format!("RIFF declares {} bytes, file has {}", riff_size + 8, len)));
+    }
+    r.seek(SeekFrom::Start(12))?;
+    let chunk = read_array::<_, 4>(r)?;
+    let _chunk_size = read_array::<_, 4>(r)?;
+    match &chunk {
+        b"VP8X" => {
+            let b = read_array::<_, 10>(r)?;
+            info.width = Some(1 + u32::from_le_bytes([b[4], b[5], b[6], 0]));
+            info.height = Some(1 + u32::from_le_bytes([b[7], b[8], b[9], 0]));
+        },
+        b"VP8L" => {
+            let b = read_array::<_, 5>(r)?;
+            if b[0] != 0x2F { return Err(Rejection::Malformed("invalid VP8L signature".to_string())); }
+            let bits = u32::from_le_bytes([b[1], b[2], b[3], b[4]]);
+            info.width = Some((bits & 0x3FFF) + 1);
+            info.height = Some(((bits >> 14) & 0x3FFF) + 1);
+        },
+        b"VP8 " => {
+            let b = read_array::<_, 10>(r)?;
+            if b[3..6] != [0x9D, 0x01, 0x2A] { return Err(Rejection::Malformed("invalid VP8 start code".to_string())); }
+            info.width = Some(u16::from_le_bytes([b[6], b[7]]) as u32 & 0x3FFF);
+            info.height = Some(u16::from_le_bytes([b[8], b[9]]) as u32 & 0x3FFF);
+        },
+        _ => return Err(Rejection::Malformed(format!("unknown WebP chunk {:?}", String::from_utf8_lossy(&chunk)))),
+    }
+    Ok(())
+}
+
+struct BoxHeader {
+    kind: [u8; 4],
+    body: u64,
+    end: u64,
+}
+
+fn read_boxes<R: Read + Seek>(r: &mut R, start: u64, end: u64) -> Result<Vec<BoxHeader>, Rejection> {
+    let mut boxes = Vec::new();
+    let mut pos = start;
+    while pos < end {
+        if end - pos < 8 { return Err(Rejection::Truncated(format!("partial box header at offset {}", pos))); }
+        r.seek(SeekFrom::Start(pos))?;
+        let size32 = read_u32(r)? as u64;
+        let kind = read_array::<_, 4>(r)?;
+        let (size, header) = match size32 {
+            0 => (end - pos, 8),
+            1 => (read_u64(r)?, 16),
+            n => (n, 8),
+        };
+        if size < header { return Err(Rejection::Malformed(format!("invalid size for box '{}'", String::from_utf8_lossy(&kind)))); }
+        if size > end - pos {
+            return Err(Rejection::Truncated(format!(
+                "box '{}' needs {} bytes, only {} available",
+                String::from_utf8_lossy(&kind), size, end - pos
+            )));
+        }
+        boxes.push(BoxHeader { kind, body: pos + header, end: pos + size });
+        pos += size;
+    }
+    Ok(boxes)
+}
+
+fn find_box<'a>(boxes: &'a [BoxHeader], kind: &[u8; 4]) -> Option<&'a BoxHeader> {
+    boxes.iter().find(|b| &b.kind == kind)
+}
+
+fn validate_mp4<R: Read + Seek>(r: &mut R, len: u64, info: &mut MediaInfo) -> Result<(), Rejection> {
+    let top = read_boxes(r, 0, len)?;
+    if top.first().map(|b| &b.kind) != Some(b"ftyp") {
+        return Err(Rejection::Malformed("MP4 does not start with an ftyp box".to_string()));
+    }
+    let moov = find_box(&top, b"moov").ok_or_else(|| Rejection::Malformed("no moov box".to_string()))?;
+    let has_data = top.iter().any(|b| &b.kind == b"mdat" && b.end > b.body);
+    if !has_data { return Err(Rejection::Malformed("no media data (mdat)".to_string())); }
+    let fragmented = find_box(&top, b"moof").is_some();
+
+    let moov_children = read_boxes(r, moov.body, moov.end)?;
+    if let Some(mvhd) = find_box(&moov_children, b"mvhd") {
+        r.seek(SeekFrom::Start(mvhd.body))?;
+        let version = read_array::<_, 4>(r)?[0];
+        let (timescale, duration) = if version == 1 {
+            r.seek(SeekFrom::Current(16))?;
+            (read_u32(r)?, read_u64(r)?)
+        } else {
+            r.seek(SeekFrom::Current(8))?;
+            (read_u32(r)?, read_u32(r)? as u64)
+        };
+        if timescale > 0 && duration > 0 { info.duration = Some(duration as f64 / timescale as f64); }
+    }
+    if info.duration.is_none() && !fragmented {
+        return Err(Rejection::Malformed("movie has zero duration".to_string()));
+    }
+
+    for trak in moov_children.iter().filter(|b| &b.kind == b"trak") {
+        let children = read_boxes(r, trak.body, trak.end)?;
+        let Some(tkhd) = find_box(&children, b"tkhd") else { continue };
+        r.seek(SeekFrom::Start(tkhd.body))?;
+        let version = read_array::<_, 4>(r)?[0];
+        r.seek(SeekFrom::Start(tkhd.body + if version == 1 { 88 } else { 76 }))?;
+        let width = read_u32(r)? >> 16;
+        let height = read_u32(r)? >> 16;
+        if width > 0 && height > 0 {
+            info.width = Some(width);
+            info.height = Some(height);
+        }
+    }
+    Ok(())
+}
+
+fn validate_heic<R: Read + Seek>(r: &mut R, len: u64, info: &mut MediaInfo) -> Result<(), Rejection> {
+    let top = read_boxes(r, 0, len)?;
+    let meta = find_box(&top, b"meta").ok_or_else(|| Rejection::Malformed("HEIF has no meta box".to_string()))?;
+    if find_box(&top, b"mdat").is_none() && find_box(&top, b"idat").is_none() {
+        return Err(Rejection::Malformed("HEIF has no image data".to_string()));
+    }
+
+    let meta_children = read_boxes(r, meta.body + 4, meta.end)?;
+    let Some(iprp) = find_box(&meta_children, b"iprp") else { return Ok(()) };
+    let iprp_children = read_boxes(r, iprp.body, iprp.end)?;
+    let Some(ipco) = find_box(&iprp_children, b"ipco") else { return Ok(()) };
+    for ispe in read_boxes(r, ipco.body, ipco.end)?.iter().filter(|b| &b.kind == b"ispe") {
+        r.seek(SeekFrom::Start(ispe.body + 4))?;
+        let width = read_u32(r)?;
+        let height = read_u32(r)?;
+        if width as u64 * height as u64 > info.width.unwrap_or(0) as u64 * info.height.unwrap_or(0) as u64 {
+            info.width = Some(width);
+            info.height = Some(height);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn check(data: &[u8]) -> Result<MediaInfo, Rejection> {
+        inspect(&mut Cursor::new(data))
+    }
+
+    fn bx(kind: &[u8; 4], parts: &[&[u8]]) -> Vec<u8> {
+        let body = parts.concat();
+        [&(body.len() as u32 + 8).to_be_bytes()[..], kind, &body].concat()
+    }
+
+    fn jpeg() -> Vec<u8> {
+        let sof = [0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00, 0x20, 0x00, 0x10, 0x03, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1];
+        let sos = [0xFF, 0xDA, 0x00, 0x0C, 0x03, 1, 0, 2, 0x11, 3, 0x11, 0x00, 0x3F, 0x00];
+        [&[0xFF, 0xD8][..], &sof, &sos, &[0x12, 0x34, 0x56], &[0xFF, 0xD9]].concat()
+    }
+
+    fn png() -> Vec<u8> {
+        let ihdr = [&13u32.to_be_bytes()[..], b"IHDR", &16u32.to_be_bytes(), &32u32.to_be_bytes(), &[8, 6, 0, 0, 0], &[0; 4]].concat();
+        let idat = [&2u32.to_be_bytes()[..], b"IDAT", &[0x78, 0x9C], &[0; 4]].concat();
+        [&b"\x89PNG\r\n\x1a\n"[..], &ihdr, &idat, b"\0\0\0\0IEND\xAE\x42\x60\x82"].concat()
+    }
+
+    fn webp(signature: u8) -> Vec<u8> {
+        let bits: u32 = 15 | (31 << 14);
+        let chunk = [&b"VP8L"[..], &5u32.to_le_bytes(), &[signature], &bits.to_le_bytes(), &[0]].concat();
+        [&b"RIFF"[..], &(chunk.len() as u32 + 4).to_le_bytes(), b"WEBP", &chunk].concat()
+    }
+
+    fn mp4() -> Vec<u8> {
+        let mvhd = bx(b"mvhd", &[&[0; 12], &1000u32.to_be_bytes(), &2500u32.to_be_bytes(), &[0; 80]]);
+        let tkhd = bx(b"tkhd", &[&[0; 76], &(720u32 << 16).to_be_bytes(), &(1280u32 << 16).to_be_bytes()]);
+        let moov = bx(b"moov", &[&mvhd, &bx(b"trak", &[&tkhd])]);
+        [bx(b"ftyp", &[b"isom", &[0; 4]]), moov, bx(b"mdat", &[&[1, 2, 3, 4]])].concat()
+    }
+
+    fn heic() -> Vec<u8> {
+        let ispe = bx(b"ispe", &[&[0; 4], &640u32.to_be_bytes(), &480u32.to_be_bytes()]);
+        let meta = bx(b"meta", &[&[0; 4], &bx(b"iprp", &[&bx(b"ipco", &[&ispe])])]);
+        [bx(b"ftyp", &[b"heic", &[0; 4]]), meta, bx(b"mdat", &[&[1, 2, 3, 4]])].concat()
+    }
+
+    fn dimensions(info: MediaInfo) -> (Option<u32>, Option<u32>) {
+        (info.width, info.height)
+    }
+
+    #[test]
+    fn accepts_minimal_files() {
+        let jpeg = check(&jpeg()).unwrap();
+        assert_eq!((jpeg.kind, dimensions(jpeg)), (MediaKind::Jpeg, (Some(16), Some(32))));
+        let png = check(&png()).unwrap();
+        assert_eq!((png.kind, dimensions(png)), (MediaKind::Png, (Some(16), Some(32))));
+        let webp = check(&webp(0x2F)).unwrap();
+        assert_eq!((webp.kind, dimensions(webp)), (MediaKind::Webp, (Some(16), Some(32))));
+        let mp4 = check(&mp4()).unwrap();
+        assert_eq!((mp4.kind, dimensions(mp4), mp4.duration), (MediaKind::Mp4, (Some(720), Some(1280)), Some(2.5)));
+        let heic = check(&heic()).unwrap();
+        assert_eq!((heic.kind, dimensions(heic)), (MediaKind::Heic, (Some(640), Some(480))));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        for (name, data) in [("jpeg", jpeg()), ("png", png()), ("webp", webp(0x2F)), ("mp4", mp4()), ("heic", heic())] {
+            let cut = &data[..data.len() - 3];
+            assert!(matches!(check(cut), Err(Rejection::Truncated(_))), "{} cut short", name);
+        }
+        assert!(matches!(check(&jpeg()[..12]), Err(Rejection::Truncated(_))));
+        assert!(matches!(check(&png()[..20]), Err(Rejection::Truncated(_))));
+        assert!(matches!(check(&mp4()[..20]), Err(Rejection::Truncated(_))));
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let mut jpeg = jpeg();
+        jpeg[21] = 0x00;
+        assert!(matches!(check(&jpeg), Err(Rejection::Malformed(_))));
+        let no_frame = [&[0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02][..], &[0xFF, 0xD9]].concat();
+        assert!(matches!(check(&no_frame), Err(Rejection::Malformed(_))));
+
+        let mut png = png();
+        png[11] = 12;
+        assert!(matches!(check(&png), Err(Rejection::Malformed(_))));
+
+        assert!(matches!(check(&webp(0x00)), Err(Rejection::Malformed(_))));
+
+        let mp4 = mp4();
+        let ftyp_len = 16;
+        let no_moov = [&mp4[..ftyp_len], &bx(b"mdat", &[&[1, 2, 3, 4]])].concat();
+        assert!(matches!(check(&no_moov), Err(Rejection::Malformed(_))));
+        let zero_size = [&mp4[..ftyp_len], &[0, 0, 0, 4], b"moov"].concat();
+        assert!(matches!(check(&zero_size), Err(Rejection::Malformed(_))));
+
+        let heic = heic();
+        let no_meta = [&heic[..ftyp_len], &bx(b"mdat", &[&[1, 2, 3, 4]])].concat();
+        assert!(matches!(check(&no_meta), Err(Rejection::Malformed(_))));
+
+        assert!(matches!(check(b"<html>error</html>"), Err(Rejection::Unrecognised)));
+    }
+
+    #[test]
+    fn rejects_oversized_largesize_boxes() {
+        let huge = [&bx(b"ftyp", &[b"isom", &[0; 4]])[..], &1u32.to_be_bytes(), b"mdat", &(u64::MAX - 4).to_be_bytes(), &[0; 8]].concat();
+        assert!(matches!(check(&huge), Err(Rejection::Truncated(_))));
+    }
+}
@@ -0,0 +1,642 @@
+use anyhow::{Result, anyhow};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrackKind {
+    Video,
+    Audio,
+    Other,
+}
+
+#[derive(Clone, Copy)]
+struct Span {
+    kind: [u8; 4],
+    start: usize,
+    body: usize,
+    end: usize,
+}
+
+struct Fragment {
+    moof: Vec<u8>,
+    moof_pos: u64,
+    mdat: (u64, u64),
+    time: f64,
+}
+
+struct Input {
+    file: File,
+    ftyp: Vec<u8>,
+    moov: Vec<u8>,
+    mdats: Vec<(u64, u64)>,
+    fragments: Vec<Fragment>,
+}
+
+// Every field access is checked against the enclosing box, whose size comes from the file.
+fn field(span: Span, at: usize, len: usize) -> Result<Range<usize>> {
+    match at.checked_add(len) {
+        Some(end) if at >= span.start && end <= span.end => Ok(at..end),
+        _ => Err(anyhow!("Truncated '{}' box at offset {}", String::from_utf8_lossy(&span.kind), span.start)),
+    }
+}
+
+fn bytes<const N: usize>(data: &[u8], span: Span, at: usize) -> Result<[u8; N]> {
+    let range = field(span, at, N)?;
+    let slice = data.get(range).ok_or_else(|| anyhow!("Box extends past the end of its buffer"))?;
+    Ok(slice.try_into()?)
+}
+
+fn be8(data: &[u8], span: Span, at: usize) -> Result<u8> {
+    Ok(bytes::<1>(data, span, at)?[0])
+}
+
+fn be32(data: &[u8], span: Span, at: usize) -> Result<u32> {
+    Ok(u32::from_be_bytes(bytes(data, span, at)?))
+}
+
+fn be64(data: &[u8], span: Span, at: usize) -> Result<u64> {
+    Ok(u64::from_be_bytes(bytes(data, span, at)?))
+}
+
+fn put(data: &mut [u8], span: Span, at: usize, value: &[u8]) -> Result<()> {
+    let range = field(span, at, value.len())?;
+    data.get_mut(range).ok_or_else(|| anyhow!("Box extends past the end of its buffer"))?.copy_from_slice(value);
+    Ok(())
+}
+
+fn put32(data: &mut [u8], span: Span, at: usize, value: u32) -> Result<()> {
+    put(data, span, at, &value.to_be_bytes())
+}
+
+fn put64(data: &mut [u8], span: Span, at: usize, value: u64) -> Result<()> {
+    put(data, span, at, &value.to_be_bytes())
+}
+
+// Start of `count` table entries of `entry` bytes after a `prefix`-byte header, if they fit in the box.
+fn table(span: Span, prefix: usize, count: usize, entry: usize) -> Result<usize> {
+    let start = span.body + prefix;
+    count.checked_mul(entry)
+        .and_then(|len| start.checked_add(len))
+        .filter(|end| *end <= span.end)
+        .map(|_| start)
+        .ok_or_else(|| anyhow!("'{}' box lists {} entries but is only {} bytes", String::from_utf8_lossy(&span.kind), count, span.end - span.start))
+}
+
+fn make_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len() + 8);
+    out.extend_from_slice(&((body.len() + 8) as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    out
+}
+
+fn children(data: &[u8], parent: Span) -> Result<Vec<Span>> {
+    let end = parent.end.min(data.len());
+    let mut spans = Vec::new();
+    let mut pos = parent.body;
+    while end - pos >= 8 {
+        let size32 = be32(data, parent, pos)? as usize;
+        let kind: [u8; 4] = bytes(data, parent, pos + 4)?;
+        let (size, header) = match size32 {
+            0 => (end - pos, 8),
+            1 => (usize::try_from(be64(data, parent, pos + 8)?).unwrap_or(usize::MAX), 16),
+            n => (n, 8),
+        };
+        if size < header || size > end - pos {
+            return Err(anyhow!("Invalid '{}' box at offset {}", String::from_utf8_lossy(&kind), pos));
+        }
+        spans.push(Span { kind, start: pos, body: pos + header, end: pos + size });
+        pos += size;
+    }
+    Ok(spans)
+}
+
+fn child(data: &[u8], parent: Span, kind: &[u8; 4]) -> Option<Span> {
+    children(data, parent).ok()?.into_iter().find(|s| &s.kind == kind)
+}
+
+fn descend(data: &[u8], parent: Span, path: &[&[u8; 4]]) -> Option<Span> {
+    path.iter().try_fold(parent, |span, kind| child(data, span, kind))
+}
+
+fn root(data: &[u8]) -> Span {
+    Span { kind: *b"root", start: 0, body: 0, end: data.len() }
+}
+
+// The same box, copied out into its own buffer.
+fn detached(span: Span) -> Span {
+    Span { kind: span.kind, start: 0, body: span.body - span.start, end: span.end - span.start }
+}
+
+struct TopBox {
+    kind: [u8; 4],
+    pos: u64,
+    header: u64,
+    size: u64,
+}
+
+fn read_top<R: Read + Seek>(r: &mut R) -> Result<Vec<TopBox>> {
+    let len = r.seek(SeekFrom::End(0))?;
+    let mut boxes = Vec::new();
+    let mut pos = 0u64;
+    while len - pos >= 8 {
+        r.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 16];
+        r.read_exact(&mut header[..8])?;
+        let kind: [u8; 4] = header[4..8].try_into().unwrap();
+        let (size, header_len) = match u32::from_be_bytes(header[..4].try_into()?) as u64 {
+            0 => (len - pos, 8),
+            1 => {
+                r.read_exact(&mut header[8..16])?;
+                (u64::from_be_bytes(header[8..16].try_into()?), 16)
+            },
+            n => (n, 8),
+        };
+        if size < header_len || size > len - pos {
+            return Err(anyhow!("Invalid '{}' box at offset {}", String::from_utf8_lossy(&kind), pos));
+        }
+        boxes.push(TopBox { kind, pos, header: header_len, size });
+        pos += size;
+    }
+    Ok(boxes)
+}
+
+fn read_range<R: Read + Seek>(r: &mut R, start: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    r.seek(SeekFrom::Start(start))?;
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn traks(moov: &[u8]) -> Result<Vec<Span>> {
+    let moov_span = children(moov, root(moov))?.into_iter().next().ok_or_else(|| anyhow!("Empty moov"))?;
+    Ok(children(moov, moov_span)?.into_iter().filter(|s| &s.kind == b"trak").collect())
+}
+
+fn handler(data: &[u8], trak: Span) -> TrackKind {
+    match descend(data, trak, &[b"mdia", b"hdlr"]) {
+        Some(hdlr) if hdlr.body + 12 <= hdlr.end => match &data[hdlr.body + 8..hdlr.body + 12] {
+            b"vide" => TrackKind::Video,
+            b"soun" => TrackKind::Audio,
+            _ => TrackKind::Other,
+        },
+        _ => TrackKind::Other,
+    }
+}
+
+fn media_timescale(data: &[u8], trak: Span) -> Result<Option<u32>> {
+    let Some(mdhd) = descend(data, trak, &[b"mdia", b"mdhd"]) else { return Ok(None) };
+    let at = if be8(data, mdhd, mdhd.body)? == 1 { mdhd.body + 20 } else { mdhd.body + 12 };
+    Ok(Some(be32(data, mdhd, at)?))
+}
+
+fn read_moov(file: &mut File) -> Result<Vec<u8>> {
+    let moov = read_top(file)?
+        .into_iter()
+        .find(|b| &b.kind == b"moov")
+        .ok_or_else(|| anyhow!("No moov box"))?;
+    Ok(read_range(file, moov.pos, moov.size as usize)?)
+}
+
+pub fn track_kinds(path: &Path) -> Result<Vec<TrackKind>> {
+    let mut file = File::open(path)?;
+    let moov = read_moov(&mut file)?;
+    Ok(traks(&moov)?.into_iter().map(|t| handler(&moov, t)).collect())
+}
+
+impl Input {
+    fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let mut input = Input { ftyp: Vec::new(), moov: Vec::new(), mdats: Vec::new(), fragments: Vec::new(), file: file.try_clone()? };
+        let mut pending: Option<(Vec<u8>, u64)> = None;
+
+        for b in read_top(&mut file)? {
+            let end = b.pos + b.size;
+            match &b.kind {
+                b"ftyp" => input.ftyp = read_range(&mut file, b.pos, b.size as usize)?,
+                b"moov" => input.moov = read_range(&mut file, b.pos, b.size as usize)?,
+                b"moof" => pending = Some((read_range(&mut file, b.pos, b.size as usize)?, b.pos)),
+                b"mdat" => match pending.take() {
+                    Some((moof, moof_pos)) => input.fragments.push(Fragment { moof, moof_pos, mdat: (b.pos, end), time: 0.0 }),
+                    None => input.mdats.push((b.pos + b.header, end)),
+                },
+                _ => {},
+            }
+        }
+        if input.ftyp.is_empty() || input.moov.is_empty() { return Err(anyhow!("{:?} is missing ftyp or moov", path)); }
+        if pending.is_some() { return Err(anyhow!("{:?} ends with a moof without data", path)); }
+
+        let trak = input.single_trak()?;
+        let timescale = media_timescale(&input.moov, trak)?.filter(|t| *t > 0).unwrap_or(1) as f64;
+        for fragment in &mut input.fragments {
+            let moof = &fragment.moof;
+            let tfdt = children(moof, root(moof))?
+                .into_iter()
+                .next()
+                .and_then(|m| descend(moof, m, &[b"traf", b"tfdt"]));
+            if let Some(tfdt) = tfdt {
+                let decode = if be8(moof, tfdt, tfdt.body)? == 1 { be64(moof, tfdt, tfdt.body + 4)? } else { be32(moof, tfdt, tfdt.body + 4)? as u64 };
+                fragment.time = decode as f64 / timescale;
+            }
+        }
+        Ok(input)
+    }
+
+    fn single_trak(&self) -> Result<Span> {
+        match traks(&self.moov)?.as_slice() {
+            [trak] => Ok(*trak),
+            other => Err(anyhow!("Expected a single-track input, found {} tracks", other.len())),
+        }
+    }
+
+    fn moov_child(&self, kind: &[u8; 4]) -> Option<Span> {
+        let moov = children(&self.moov, root(&self.moov)).ok()?.into_iter().next()?;
+        child(&self.moov, moov, kind)
+    }
+
+    fn movie_timescale(&self) -> Result<(u32, u64)> {
+        let mvhd = self.moov_child(b"mvhd").ok_or_else(|| anyhow!("No mvhd box"))?;
+        let data = &self.moov;
+        Ok(if be8(data, mvhd, mvhd.body)? == 1 {
+            (be32(data, mvhd, mvhd.body + 20)?, be64(data, mvhd, mvhd.body + 24)?)
+        } else {
+            (be32(data, mvhd, mvhd.body + 12)?, be32(data, mvhd, mvhd.body + 16)? as u64)
+        })
+    }
+}
+
+fn rescale(value: u64, from: u32, to: u32) -> u64 {
+    if from == to || from == 0 { value } else { (value as u128 * to as u128 / from as u128) as u64 }
+}
+
+fn rewrite_trak(trak: &mut [u8], track_id: u32, from: u32, to: u32) -> Result<()> {
+    let trak_span = children(trak, root(trak))?.into_iter().next().ok_or_else(|| anyhow!("Empty trak"))?;
+
+    let tkhd = child(trak, trak_span, b"tkhd").ok_or_else(|| anyhow!("No tkhd box"))?;
+    if be8(trak, tkhd, tkhd.body)? == 1 {
+        put32(trak, tkhd, tkhd.body + 20, track_id)?;
+        let duration = rescale(be64(trak, tkhd, tkhd.body + 28)?, from, to);
+        put64(trak, tkhd, tkhd.body + 28, duration)?;
+    } else {
+        put32(trak, tkhd, tkhd.body + 12, track_id)?;
+        let duration = rescale(be32(trak, tkhd, tkhd.body + 20)? as u64, from, to);
+        put32(trak, tkhd, tkhd.body + 20, duration.min(u32::MAX as u64) as u32)?;
+    }
+
+    if let Some(elst) = descend(trak, trak_span, &[b"edts", b"elst"]) {
+        let version = be8(trak, elst, elst.body)?;
+        let count = be32(trak, elst, elst.body + 4)? as usize;
+        let entry = if version == 1 { 20 } else { 12 };
+        let first = table(elst, 8, count, entry)?;
+        for i in 0..count {
+            let at = first + i * entry;
+            if version == 1 {
+                put64(trak, elst, at, rescale(be64(trak, elst, at)?, from, to))?;
+            } else {
+                put32(trak, elst, at, rescale(be32(trak, elst, at)? as u64, from, to).min(u32::MAX as u64) as u32)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn relocate_chunks(trak: &mut [u8], map: &[(u64, u64, u64)]) -> Result<()> {
+    let trak_span = children(trak, root(trak))?.into_iter().next().ok_or_else(|| anyhow!("Empty trak"))?;
+    let stbl = descend(trak, trak_span, &[b"mdia", b"minf", b"stbl"]).ok_or_else(|| anyhow!("No stbl box"))?;
+    let relocate = |offset: u64| -> Result<u64> {
+        map.iter()
+            .find(|(start, end, _)| offset >= *start && offset < *end)
+            .and_then(|(start, _, new)| (offset - start).checked_add(*new))
+            .ok_or_else(|| anyhow!("Chunk offset {} lies outside the media data", offset))
+    };
+
+    if let Some(stco) = child(trak, stbl, b"stco") {
+        let count = be32(trak, stco, stco.body + 4)? as usize;
+        let first = table(stco, 8, count, 4)?;
+        for i in 0..count {
+            let at = first + i * 4;
+            let offset = relocate(be32(trak, stco, at)? as u64)?;
+            let offset = u32::try_from(offset).map_err(|_| anyhow!("Muxed file is too large for 32-bit chunk offsets"))?;
+            put32(trak, stco, at, offset)?;
+        }
+    } else if let Some(co64) = child(trak, stbl, b"co64") {
+        let count = be32(trak, co64, co64.body + 4)? as usize;
+        let first = table(co64, 8, count, 8)?;
+        for i in 0..count {
+            let at = first + i * 8;
+            let offset = relocate(be64(trak, co64, at)?)?;
+            put64(trak, co64, at, offset)?;
+        }
+    } else {
+        return Err(anyhow!("No chunk offset table"));
+    }
+    Ok(())
+}
+
+fn patch_fragment(moof: &mut [u8], sequence: u32, track_id: u32, old_pos: u64, new_pos: u64) -> Result<()> {
+    let moof_span = children(moof, root(moof))?.into_iter().next().ok_or_else(|| anyhow!("Empty moof"))?;
+    if let Some(mfhd) = child(moof, moof_span, b"mfhd") { put32(moof, mfhd, mfhd.body + 4, sequence)?; }
+    for traf in children(moof, moof_span)?.into_iter().filter(|s| &s.kind == b"traf") {
+        let tfhd = child(moof, traf, b"tfhd").ok_or_else(|| anyhow!("No tfhd box"))?;
+        put32(moof, tfhd, tfhd.body + 4, track_id)?;
+        let flags = be32(moof, tfhd, tfhd.body)? & 0x00FF_FFFF;
+        if flags & 0x1 != 0 {
+            let base = be64(moof, tfhd, tfhd.body + 8)?;
+            let relative = base.checked_sub(old_pos).ok_or_else(|| anyhow!("Fragment data lies before its moof"))?;
+            let moved = new_pos.checked_add(relative).ok_or_else(|| anyhow!("Fragment data offset overflows"))?;
+            put64(moof, tfhd, tfhd.body + 8, moved)?;
+        }
+    }
+    Ok(())
+}
+
+fn copy_range(file: &mut File, out: &mut impl Write, start: u64, end: u64) -> io::Result<()> {
+    file.seek(SeekFrom::Start(start))?;
+    io::copy(&mut file.take(end - start), out)?;
+    Ok(())
+}
+
+/// Combine a video-only and an audio-only MP4 into one file without re-encoding.
+/// Both inputs must be either fragmented (DASH) or progressive.
+pub fn mux(video: &Path, audio: &Path, out: &mut File) -> Result<()> {
+    let mut v = Input::open(video)?;
+    let mut a = Input::open(audio)?;
+    let fragmented = !v.fragments.is_empty();
+    if fragmented == a.fragments.is_empty() {
+        return Err(anyhow!("Cannot mux a fragmented stream with a progressive one"));
+    }
+
+    let vtrak = v.single_trak()?;
+    let atrak = a.single_trak()?;
+    let (v_scale, v_duration) = v.movie_timescale()?;
+    let (a_scale, a_duration) = a.movie_timescale()?;
+
+    let mut vtrak_bytes = v.moov[vtrak.start..vtrak.end].to_vec();
+    let mut atrak_bytes = a.moov[atrak.start..atrak.end].to_vec();
+    rewrite_trak(&mut vtrak_bytes, 1, v_scale, v_scale)?;
+    rewrite_trak(&mut atrak_bytes, 2, a_scale, v_scale)?;
+
+    let mvhd = v.moov_child(b"mvhd").ok_or_else(|| anyhow!("No mvhd box"))?;
+    let mut mvhd_bytes = v.moov[mvhd.start..mvhd.end].to_vec();
+    let local = detached(mvhd);
+    let duration = v_duration.max(rescale(a_duration, a_scale, v_scale));
+    if be8(&mvhd_bytes, local, local.body)? == 1 {
+        put64(&mut mvhd_bytes, local, local.body + 24, duration)?;
+    } else {
+        put32(&mut mvhd_bytes, local, local.body + 16, duration.min(u32::MAX as u64) as u32)?;
+    }
+    let next_id_at = local.end.checked_sub(4).filter(|at| *at >= local.body + 20).ok_or_else(|| anyhow!("Truncated 'mvhd' box"))?;
+    put32(&mut mvhd_bytes, local, next_id_at, 3)?;
+
+    let mut mvex = Vec::new();
+    if fragmented {
+        for (input, id) in [(&v, 1u32), (&a, 2u32)] {
+            let mvex_span = input.moov_child(b"mvex").ok_or_else(|| anyhow!("Fragmented input has no mvex box"))?;
+            let trex = child(&input.moov, mvex_span, b"trex").ok_or_else(|| anyhow!("No trex box"))?;
+            let mut trex_bytes = input.moov[trex.start..trex.end].to_vec();
+            let local = detached(trex);
+            put32(&mut trex_bytes, local, local.body + 4, id)?;
+            mvex.extend_from_slice(&trex_bytes);
+        }
+        mvex = make_box(b"mvex", &mvex);
+    }
+    let udta = v.moov_child(b"udta").map(|s| v.moov[s.start..s.end].to_vec()).unwrap_or_default();
+
+    let moov_len = 8 + mvhd_bytes.len() + vtrak_bytes.len() + atrak_bytes.len() + mvex.len() + udta.len();
+    let header_len = (v.ftyp.len() + moov_len) as u64;
+
+    if !fragmented {
+        let payload: u64 = v.mdats.iter().chain(&a.mdats).map(|(s, e)| e - s).sum();
+        let large = payload + 8 > u32::MAX as u64;
+        let mut cursor = header_len + if large { 16 } else { 8 };
+        let mut map = |ranges: &[(u64, u64)]| -> Vec<(u64, u64, u64)> {
+            ranges.iter().map(|&(s, e)| { let entry = (s, e, cursor); cursor += e - s; entry }).collect()
+        };
+        let v_map = map(&v.mdats);
+        let a_map = map(&a.mdats);
+        relocate_chunks(&mut vtrak_bytes, &v_map)?;
+        relocate_chunks(&mut atrak_bytes, &a_map)?;
+
+        let moov = make_box(b"moov", &[mvhd_bytes, vtrak_bytes, atrak_bytes, udta].concat());
+        out.write_all(&v.ftyp)?;
+        out.write_all(&moov)?;
+        if large {
+            out.write_all(&1u32.to_be_bytes())?;
+            out.write_all(b"mdat")?;
+            out.write_all(&(payload + 16).to_be_bytes())?;
+        } else {
+            out.write_all(&((payload + 8) as u32).to_be_bytes())?;
+            out.write_all(b"mdat")?;
+        }
+        for (s, e) in v.mdats.clone() { copy_range(&mut v.file, out, s, e)?; }
+        for (s, e) in a.mdats.clone() { copy_range(&mut a.file, out, s, e)?; }
+        return Ok(());
+    }
+
+    let moov = make_box(b"moov", &[mvhd_bytes, vtrak_bytes, atrak_bytes, mvex, udta].concat());
+    out.write_all(&v.ftyp)?;
+    out.write_all(&moov)?;
+
+    let mut order: Vec<(f64, usize, usize)> = v.fragments.iter().enumerate().map(|(i, f)| (f.time, 0, i))
+        .chain(a.fragments.iter().enumerate().map(|(i, f)| (f.time, 1, i)))
+        .collect();
+    order.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut pos = header_len;
+    for (sequence, (_, which, idx)) in order.into_iter().enumerate() {
+        let input = if which == 0 { &mut v } else { &mut a };
+        let fragment = &input.fragments[idx];
+        let mut moof = fragment.moof.clone();
+        patch_fragment(&mut moof, sequence as u32 + 1, which as u32 + 1, fragment.moof_pos, pos)?;
+        let (mdat_start, mdat_end) = fragment.mdat;
+        out.write_all(&moof)?;
+        copy_range(&mut input.file, out, mdat_start, mdat_end)?;
+        pos += moof.len() as u64 + (mdat_end - mdat_start);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn bx(kind: &[u8; 4], parts: &[&[u8]]) -> Vec<u8> {
+        make_box(kind, &parts.concat())
+    }
+
+    fn full(kind: &[u8; 4], version: u8, parts: &[&[u8]]) -> Vec<u8> {
+        bx(kind, &[&[version, 0, 0, 0], &parts.concat()])
+    }
+
+    fn mvhd(timescale: u32, duration: u32) -> Vec<u8> {
+        full(b"mvhd", 0, &[&[0; 8], &timescale.to_be_bytes(), &duration.to_be_bytes(), &[0; 76], &7u32.to_be_bytes()])
+    }
+
+    fn trak(handler: &[u8; 4], track_id: u32, timescale: u32, duration: u32, stco: &[u32]) -> Vec<u8> {
+        let tkhd = full(b"tkhd", 0, &[&[0; 8], &track_id.to_be_bytes(), &[0; 4], &duration.to_be_bytes(), &[0; 60]]);
+        let mdhd = full(b"mdhd", 0, &[&[0; 8], &timescale.to_be_bytes(), &duration.to_be_bytes(), &[0; 4]]);
+        let hdlr = full(b"hdlr", 0, &[&[0; 4], handler, &[0; 13]]);
+        let offsets: Vec<u8> = stco.iter().flat_map(|o| o.to_be_bytes()).collect();
+        let stco = full(b"stco", 0, &[&(stco.len() as u32).to_be_bytes(), &offsets]);
+        let stbl = bx(b"stbl", &[&stco]);
+        let minf = bx(b"minf", &[&stbl]);
+        let mdia = bx(b"mdia", &[&mdhd, &hdlr, &minf]);
+        bx(b"trak", &[&tkhd, &mdia])
+    }
+
+    fn ftyp() -> Vec<u8> {
+        bx(b"ftyp", &[b"isom", &[0; 4], b"isomiso6"])
+    }
+
+    // ftyp + moov + one mdat holding `payload`, with a single chunk pointing at it.
+    fn progressive(handler: &[u8; 4], timescale: u32, duration: u32, payload: &[u8]) -> Vec<u8> {
+        let ftyp = ftyp();
+        let moov_len = bx(b"moov", &[&mvhd(timescale, duration), &trak(handler, 9, timescale, duration, &[0])]).len();
+        let offset = (ftyp.len() + moov_len + 8) as u32;
+        let moov = bx(b"moov", &[&mvhd(timescale, duration), &trak(handler, 9, timescale, duration, &[offset])]);
+        [ftyp, moov, bx(b"mdat", &[payload])].concat()
+    }
+
+    // ftyp + moov(mvex) + one moof/mdat pair per (decode time, payload), with tfhd base_data_offset set.
+    fn fragmented(handler: &[u8; 4], timescale: u32, fragments: &[(u64, &[u8])]) -> Vec<u8> {
+        let trex = full(b"trex", 0, &[&9u32.to_be_bytes(), &[0; 16]]);
+        let moov = bx(b"moov", &[&mvhd(timescale, 0), &trak(handler, 9, timescale, 0, &[]), &bx(b"mvex", &[&trex])]);
+        let mut out = [ftyp(), moov].concat();
+        for (seq, (time, payload)) in fragments.iter().enumerate() {
+            let pos = out.len() as u64;
+            let mfhd = full(b"mfhd", 0, &[&(seq as u32 + 5).to_be_bytes()]);
+            let tfhd = bx(b"tfhd", &[&[0, 0, 0, 1], &9u32.to_be_bytes(), &pos.to_be_bytes()]);
+            let tfdt = full(b"tfdt", 1, &[&time.to_be_bytes()]);
+            out.extend(bx(b"moof", &[&mfhd, &bx(b"traf", &[&tfhd, &tfdt])]));
+            out.extend(bx(b"mdat", &[payload]));
+        }
+        out
+    }
+
+    fn temp_file(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("stov-mp4-{}-{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    fn mux_bytes(name: &str, video: &[u8], audio: &[u8]) -> Result<Vec<u8>> {
+        let v = temp_file(&format!("{}-v.mp4", name), video);
+        let a = temp_file(&format!("{}-a.mp4", name), audio);
+        let o = std::env::temp_dir().join(format!("stov-mp4-{}-{}-out.mp4", std::process::id(), name));
+        let result = File::create(&o).map_err(anyhow::Error::from).and_then(|mut out| mux(&v, &a, &mut out));
+        let data = std::fs::read(&o);
+        for path in [&v, &a, &o] { let _ = std::fs::remove_file(path); }
+        result.map(|_| data.unwrap())
+    }
+
+    fn top(data: &[u8]) -> Vec<TopBox> {
+        read_top(&mut Cursor::new(data)).unwrap()
+    }
+
+    fn moov_of(data: &[u8]) -> Vec<u8> {
+        let moov = top(data).into_iter().find(|b| &b.kind == b"moov").unwrap();
+        data[moov.pos as usize..(moov.pos + moov.size) as usize].to_vec()
+    }
+
+    fn track_id(moov: &[u8], trak: Span) -> u32 {
+        let tkhd = child(moov, trak, b"tkhd").unwrap();
+        be32(moov, tkhd, tkhd.body + 12).unwrap()
+    }
+
+    #[test]
+    fn muxes_progressive_inputs() {
+        let video = progressive(b"vide", 1000, 2000, b"VIDEO-SAMPLES");
+        let audio = progressive(b"soun", 44100, 88200, b"AUDIO");
+        let out = mux_bytes("progressive", &video, &audio).unwrap();
+
+        let moov = moov_of(&out);
+        let traks = traks(&moov).unwrap();
+        assert_eq!(traks.iter().map(|t| handler(&moov, *t)).collect::<Vec<_>>(), [TrackKind::Video, TrackKind::Audio]);
+        assert_eq!(traks.iter().map(|t| track_id(&moov, *t)).collect::<Vec<_>>(), [1, 2]);
+
+        // Audio duration is rescaled to the video's movie timescale.
+        let tkhd = child(&moov, traks[1], b"tkhd").unwrap();
+        assert_eq!(be32(&moov, tkhd, tkhd.body + 20).unwrap(), 2000);
+
+        for (trak, expected) in traks.iter().zip([&b"VIDEO-SAMPLES"[..], b"AUDIO"]) {
+            let stco = descend(&moov, *trak, &[b"mdia", b"minf", b"stbl", b"stco"]).unwrap();
+            let offset = be32(&moov, stco, stco.body + 8).unwrap() as usize;
+            assert_eq!(&out[offset..offset + expected.len()], expected);
+        }
+        assert_eq!(top(&out).iter().filter(|b| &b.kind == b"mdat").count(), 1);
+    }
+
+    #[test]
+    fn muxes_fragmented_inputs() {
+        let video = fragmented(b"vide", 1000, &[(0, b"V0"), (2000, b"V1")]);
+        let audio = fragmented(b"soun", 48000, &[(48000, b"A0")]);
+        let out = mux_bytes("fragmented", &video, &audio).unwrap();
+
+        let moov = moov_of(&out);
+        let traks = traks(&moov).unwrap();
+        assert_eq!(traks.iter().map(|t| track_id(&moov, *t)).collect::<Vec<_>>(), [1, 2]);
+
+        let boxes = top(&out);
+        let moofs: Vec<&TopBox> = boxes.iter().filter(|b| &b.kind == b"moof").collect();
+        assert_eq!(moofs.len(), 3);
+        let mut seen = Vec::new();
+        for (i, moof_box) in moofs.iter().enumerate() {
+            let moof = &out[moof_box.pos as usize..(moof_box.pos + moof_box.size) as usize];
+            let moof_span = children(moof, root(moof)).unwrap()[0];
+            let mfhd = child(moof, moof_span, b"mfhd").unwrap();
+            assert_eq!(be32(moof, mfhd, mfhd.body + 4).unwrap(), i as u32 + 1);
+            let tfhd = descend(moof, moof_span, &[b"traf", b"tfhd"]).unwrap();
+            let id = be32(moof, tfhd, tfhd.body + 4).unwrap();
+            assert_eq!(be64(moof, tfhd, tfhd.body + 8).unwrap(), moof_box.pos);
+            let mdat = (moof_box.pos + moof_box.size + 8) as usize;
+            seen.push((id, out[mdat..mdat + 2].to_vec()));
+        }
+        // Fragments are interleaved by decode time: V0 at 0s, A0 at 1s, V1 at 2s.
+        assert_eq!(seen, [(1, b"V0".to_vec()), (2, b"A0".to_vec()), (1, b"V1".to_vec())]);
+    }
+
+    #[test]
+    fn rejects_mixed_layouts() {
+        let video = progressive(b"vide", 1000, 2000, b"V");
+        let audio = fragmented(b"soun", 48000, &[(0, b"A")]);
+        assert!(mux_bytes("mixed", &video, &audio).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_inputs() {
+        let video = progressive(b"vide", 1000, 2000, b"VIDEO");
+        let audio = progressive(b"soun", 44100, 88200, b"AUDIO");
+
+        // stco entry count far larger than the box.
+        let mut huge_count = audio.clone();
+        let at = huge_count.windows(4).position(|w| w == b"stco").unwrap() + 8;
+        huge_count[at..at + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(mux_bytes("count", &video, &huge_count).is_err());
+
+        // Truncated in the middle of the moov.
+        assert!(mux_bytes("truncated", &video, &audio[..audio.len() - 40]).is_err());
+
+        // A 64-bit largesize near u64::MAX.
+        let mut large = video.clone();
+        large.extend_from_slice(&1u32.to_be_bytes());
+        large.extend_from_slice(b"free");
+        large.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+        assert!(mux_bytes("largesize", &large, &audio).is_err());
+
+        // tkhd cut short inside an otherwise consistent trak.
+        let mut short_tkhd = audio.clone();
+        let at = short_tkhd.windows(4).position(|w| w == b"tkhd").unwrap() - 4;
+        short_tkhd[at..at + 4].copy_from_slice(&16u32.to_be_bytes());
+        assert!(mux_bytes("tkhd", &video, &short_tkhd).is_err());
+
+        // Fragmented input whose tfdt is cut short.
+        let frag = fragmented(b"vide", 1000, &[(0, b"V0")]);
+        let mut short_tfdt = frag.clone();
+        let at = short_tfdt.windows(4).position(|w| w == b"tfdt").unwrap() - 4;
+        short_tfdt[at..at + 4].copy_from_slice(&9u32.to_be_bytes());
+        assert!(mux_bytes("tfdt", &short_tfdt, &fragmented(b"soun", 48000, &[(0, b"A0")])).is_err());
+    }
+}
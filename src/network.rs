@@ -0,0 +1,121 @@
+use anyhow::Result;
+use headless_chrome::Tab;
+use headless_chrome::protocol::cdp::Network::{self, ResourceType};
+use headless_chrome::protocol::cdp::types::Event;
+use std::sync::{Arc, Mutex};
+
+const MAX_ENTRIES: usize = 500;
+const MEDIA_EXTENSIONS: &[&str] = &[".mp4", ".jpg", ".jpeg", ".webp", ".heic", ".png"];
+
+#[derive(Clone)]
+pub struct NetworkEntry {
+    pub request_id: String,
+    pub url: String,
+    pub mime: Option<String>,
+    pub status: Option<u32>,
+    pub size: Option<u64>,
+    pub from_cache: bool,
+}
+
+impl NetworkEntry {
+    fn is_media(&self) -> bool {
+        match &self.mime {
+            Some(mime) => ["image/", "video/", "audio/"].iter().any(|prefix| mime.starts_with(prefix)),
+            None => {
+                let path = self.url.split('?').next().unwrap_or("").to_lowercase();
+                MEDIA_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+            },
+        }
+    }
+}
+
+// Media requests of the tab, collected from CDP Network events while a story batch runs.
+#[derive(Clone, Default)]
+pub struct NetworkLog {
+    entries: Arc<Mutex<Vec<NetworkEntry>>>,
+}
+
+impl NetworkLog {
+    pub fn attach(tab: &Tab) -> Result<Self> {
+        let log = Self::default();
+        let entries = Arc::clone(&log.entries);
+        tab.add_event_listener(Arc::new(move |event: &Event| {
+            let mut entries = entries.lock().unwrap();
+            handle(&mut entries, event);
+        }))?;
+        Ok(log)
+    }
+
+    pub fn start(&self, tab: &Tab) -> Result<()> {
+        self.clear();
+        tab.call_method(Network::Enable {
+            max_total_buffer_size: None,
+            max_resource_buffer_size: None,
+            max_post_data_size: None,
+            report_direct_socket_traffic: None,
+            enable_durable_messages: None,
+        })?;
+        Ok(())
+    }
+
+    pub fn stop(&self, tab: &Tab) -> Result<()> {
+        tab.call_method(Network::Disable(None))?;
+        self.clear();
+        Ok(())
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    // Successful media responses and media requests still in flight, newest first.
+    pub fn media(&self) -> Vec<NetworkEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .rev()
+            .filter(|e| e.is_media() && e.status.is_none_or(|s| s < 400))
+            .cloned()
+            .collect()
+    }
+}
+
+fn handle(entries: &mut Vec<NetworkEntry>, event: &Event) {
+    match event {
+        Event::NetworkRequestWillBeSent(ev) => {
+            let params = &ev.params;
+            let wanted = matches!(params.Type, None | Some(ResourceType::Image | ResourceType::Media | ResourceType::Xhr | ResourceType::Fetch | ResourceType::Other));
+            if !wanted || !params.request.url.starts_with("http") { return; }
+            // Redirects reuse the request id with the new URL.
+            entries.retain(|e| e.request_id != params.request_id);
+            if entries.len() >= MAX_ENTRIES { entries.remove(0); }
+            entries.push(NetworkEntry {
+                request_id: params.request_id.clone(),
+                url: params.request.url.clone(),
+                mime: None,
+                status: None,
+                size: None,
+                from_cache: false,
+            });
+        },
+        Event::NetworkResponseReceived(ev) => {
+            let response = &ev.params.response;
+            let Some(entry) = entries.iter_mut().find(|e| e.request_id == ev.params.request_id) else { return };
+            entry.mime = Some(response.mime_type.to_lowercase());
+            entry.status = Some(response.status);
+            entry.from_cache = response.from_disk_cache.unwrap_or(false) || response.from_prefetch_cache.unwrap_or(false);
+            if !entry.is_media() { entries.retain(|e| e.request_id != ev.params.request_id); }
+        },
+        Event::NetworkRequestServedFromCache(ev) => {
+            if let Some(entry) = entries.iter_mut().find(|e| e.request_id == ev.params.request_id) { entry.from_cache = true; }
+        },
+        Event::NetworkLoadingFinished(ev) => {
+            if let Some(entry) = entries.iter_mut().find(|e| e.request_id == ev.params.request_id) {
+                entry.size = Some(ev.params.encoded_data_length as u64);
+            }
+        },
+        Event::NetworkLoadingFailed(ev) => entries.retain(|e| e.request_id != ev.params.request_id),
+        _ => {},
+    }
+}
+
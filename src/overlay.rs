@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+const RESERVED_PATHS: &[&str] = &["explore", "stories", "accounts", "direct", "reels", "reel", "p", "about", "legal", "developer"];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Location {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Music {
+    pub id: String,
+    pub title: String,
+}
+
+// Stickers and tags drawn on top of a story slide.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Overlays {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hashtags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub locations: Vec<Location>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub music: Option<Music>,
+}
+
+fn dedup<T>(values: &mut Vec<T>, key: impl Fn(&T) -> &str) {
+    let mut seen: Vec<String> = Vec::new();
+    values.retain(|v| {
+        let key = key(v);
+        if key.is_empty() || seen.iter().any(|s| s == key) { return false; }
+        seen.push(key.to_string());
+        true
+    });
+}
+
+impl Overlays {
+    pub fn is_empty(&self) -> bool {
+        self.mentions.is_empty() && self.hashtags.is_empty() && self.links.is_empty() && self.locations.is_empty() && self.music.is_none()
+    }
+
+    pub fn from_json(value: &serde_json::Value, owner: &str) -> Self {
+        let mut overlays: Self = serde_json::from_value(value.clone()).unwrap_or_default();
+        for mention in &mut overlays.mentions { *mention = mention.trim_start_matches('@').to_lowercase(); }
+        overlays.mentions.retain(|m| {
+            m != &owner.to_lowercase()
+                && !RESERVED_PATHS.contains(&m.as_str())
+                && m.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_')
+        });
+        for tag in &mut overlays.hashtags { *tag = tag.trim_start_matches('#').to_lowercase(); }
+        overlays.links.retain(|l| l.starts_with("https://") || l.starts_with("http://"));
+        dedup(&mut overlays.mentions, |m| m);
+        dedup(&mut overlays.hashtags, |t| t);
+        dedup(&mut overlays.links, |l| l);
+        dedup(&mut overlays.locations, |l| &l.id);
+        overlays
+    }
+
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        for (count, name) in [(self.mentions.len(), "mentions"), (self.hashtags.len(), "hashtags"), (self.links.len(), "links"), (self.locations.len(), "locations")] {
+            if count > 0 { parts.push(format!("{}: {}", name, count)); }
+        }
+        if let Some(music) = &self.music { parts.push(format!("music \"{}\"", music.title)); }
+        parts.join(", ")
+    }
+}
+
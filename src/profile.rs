@@ -0,0 +1,503 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Result, anyhow};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{Engine as _, engine::general_purpose};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use headless_chrome::protocol::cdp::Network::{Cookie, CookieParam, CookieSameSite};
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+use tracing::info;
+use crate::config;
+use crate::credentials::{CredentialSource, prompt_hidden};
+use crate::utils;
+
+const ENCRYPTED_FORMAT: &str = "stov-encrypted-v1";
+
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StoredCookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<f64>,
+    pub secure: bool,
+    pub http_only: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub same_site: Option<CookieSameSite>,
+}
+
+impl From<&Cookie> for StoredCookie {
+    fn from(c: &Cookie) -> Self {
+        Self {
+            name: c.name.clone(),
+            value: c.value.clone(),
+            domain: c.domain.clone(),
+            path: c.path.clone(),
+            expires: if c.session { None } else { Some(c.expires) },
+            secure: c.secure,
+            http_only: c.http_only,
+            same_site: c.same_site.clone(),
+        }
+    }
+}
+
+impl StoredCookie {
+    fn session(session_id: &str) -> Self {
+        Self {
+            name: "sessionid".to_string(),
+            value: session_id.to_string(),
+            domain: ".instagram.com".to_string(),
+            path: "/".to_string(),
+            expires: None,
+            secure: true,
+            http_only: true,
+            same_site: None,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+        matches!(self.expires, Some(t) if t > 0.0 && t < now)
+    }
+
+    pub fn to_param(&self) -> CookieParam {
+        let host = self.domain.trim_start_matches('.');
+        CookieParam {
+            name: self.name.clone(),
+            value: self.value.clone(),
+            url: Some(format!("https://{}{}", host, self.path)),
+            domain: Some(self.domain.clone()),
+            path: Some(self.path.clone()),
+            secure: Some(self.secure),
+            http_only: Some(self.http_only),
+            same_site: self.same_site.clone(),
+            expires: self.expires,
+            priority: None,
+            source_scheme: None,
+            source_port: None,
+            partition_key: None,
+            same_party: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ProfileMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_verified: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_status: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserProfile {
+    pub username: String,
+    #[serde(default)]
+    pub cookies: Vec<StoredCookie>,
+    #[serde(default, flatten)]
+    pub meta: ProfileMeta,
+    #[serde(default, skip_serializing)]
+    session_id: Option<String>,
+}
+
+impl UserProfile {
+    fn upgrade(mut self) -> Self {
+        if let Some(sid) = self.session_id.take() {
+            if !self.cookies.iter().any(|c| c.name == "sessionid") {
+                self.cookies.push(StoredCookie::session(&sid));
+            }
+        }
+        self
+    }
+
+    pub fn has_session(&self) -> bool {
+        self.cookies.iter().any(|c| c.name == "sessionid" && !c.is_expired())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct KdfParams {
+    algorithm: String,
+    salt: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedProfile {
+    format: String,
+    #[serde(default, flatten)]
+    meta: ProfileMeta,
+    kdf: KdfParams,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredProfile {
+    Encrypted(EncryptedProfile),
+    Plain(UserProfile),
+}
+
+impl StoredProfile {
+    fn meta(&self) -> ProfileMeta {
+        match self {
+            StoredProfile::Encrypted(envelope) => envelope.meta.clone(),
+            StoredProfile::Plain(profile) => profile.meta.clone(),
+        }
+    }
+}
+
+pub struct ProfileSummary {
+    pub name: String,
+    pub encrypted: bool,
+    pub meta: ProfileMeta,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn profile_path(username: &str) -> Result<PathBuf> {
+    validate_name(username)?;
+    Ok(Path::new(&config::get().paths.profiles_dir).join(format!("{}.json", username)))
+}
+
+fn read_passphrase(label: &str) -> Result<String> {
+    config::get().profiles.passphrase.read(label).map_err(|e| anyhow!("Failed to read profile passphrase: {}", e))
+}
+
+// The passphrase is only remembered once it has decrypted a profile or been confirmed as a new key.
+fn unlock(envelope: &EncryptedProfile) -> Result<(UserProfile, &'static str)> {
+    if let Some(p) = PASSPHRASE.get() { return Ok((decrypt(envelope, p)?, p)); }
+    let value = read_passphrase("Profile Passphrase:")?;
+    let profile = decrypt(envelope, &value)?;
+    Ok((profile, PASSPHRASE.get_or_init(|| value)))
+}
+
+fn stored_envelope() -> Option<EncryptedProfile> {
+    list_profiles().ok()?.into_iter().find_map(|name| match read_stored(&name) {
+        Ok(StoredProfile::Encrypted(envelope)) => Some(envelope),
+        _ => None,
+    })
+}
+
+// Passphrase for encrypting: checked against an existing encrypted profile, or entered twice when it sets a new key.
+fn new_passphrase() -> Result<&'static str> {
+    if let Some(envelope) = stored_envelope() {
+        return unlock(&envelope).map(|(_, p)| p);
+    }
+    if let Some(p) = PASSPHRASE.get() { return Ok(p); }
+    let value = read_passphrase("New Profile Passphrase:")?;
+    if matches!(config::get().profiles.passphrase, CredentialSource::Prompt) && prompt_hidden("Repeat Passphrase:")? != value {
+        return Err(anyhow!("Passphrases do not match"));
+    }
+    Ok(PASSPHRASE.get_or_init(|| value))
+}
+
+fn derive_key(passphrase: &str, kdf: &KdfParams) -> Result<[u8; 32]> {
+    if kdf.algorithm != "argon2id" { return Err(anyhow!("Unsupported key derivation: {}", kdf.algorithm)); }
+    let salt = general_purpose::STANDARD.decode(&kdf.salt)?;
+    let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32)).map_err(|e| anyhow!("Invalid KDF parameters: {}", e))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+fn encrypt(profile: &UserProfile, passphrase: &str) -> Result<EncryptedProfile> {
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+    let defaults = Params::default();
+    let kdf = KdfParams {
+        algorithm: "argon2id".to_string(),
+        salt: general_purpose::STANDARD.encode(salt),
+        m_cost: defaults.m_cost(),
+        t_cost: defaults.t_cost(),
+        p_cost: defaults.p_cost(),
+    };
+
+    let key = derive_key(passphrase, &kdf)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let plaintext = serde_json::to_vec(profile)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| anyhow!("Profile encryption failed"))?;
+
+    Ok(EncryptedProfile {
+        format: ENCRYPTED_FORMAT.to_string(),
+        meta: profile.meta.clone(),
+        kdf,
+        nonce: general_purpose::STANDARD.encode(nonce),
+        ciphertext: general_purpose::STANDARD.encode(ciphertext),
+    })
+}
+
+fn decrypt(envelope: &EncryptedProfile, passphrase: &str) -> Result<UserProfile> {
+    if envelope.format != ENCRYPTED_FORMAT { return Err(anyhow!("Unsupported profile format: {}", envelope.format)); }
+    let key = derive_key(passphrase, &envelope.kdf)?;
+    let nonce = general_purpose::STANDARD.decode(&envelope.nonce)?;
+    if nonce.len() != 12 { return Err(anyhow!("Corrupted profile (bad nonce)")); }
+    let ciphertext = general_purpose::STANDARD.decode(&envelope.ciphertext)?;
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| anyhow!("Failed to decrypt profile (wrong passphrase or tampered file)"))?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+pub fn ensure_profiles_dir() -> Result<()> {
+    let dir = Path::new(&config::get().paths.profiles_dir);
+    if !dir.exists() { fs::create_dir_all(dir)?; }
+    restrict_permissions(dir, 0o700)
+}
+
+// Written to a 0600 temp file and renamed over the target, so a failed write never leaves a truncated profile.
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    let temp = utils::temp_path(utils::parent_dir(path));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options.open(&temp).map_err(anyhow::Error::from).and_then(|mut file| {
+        restrict_permissions(&temp, 0o600)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        utils::move_into_place(&temp, path)
+    });
+    if written.is_err() { let _ = fs::remove_file(&temp); }
+    written
+}
+
+fn write_profile(profile: &UserProfile, encrypted: bool) -> Result<()> {
+    ensure_profiles_dir()?;
+
+    let json = if encrypted {
+        serde_json::to_string_pretty(&encrypt(profile, new_passphrase()?)?)?
+    } else {
+        serde_json::to_string_pretty(profile)?
+    };
+    write_private(&profile_path(&profile.username)?, &json)
+}
+
+pub fn save_profile(username: &str, cookies: Vec<StoredCookie>) -> Result<()> {
+    let mut meta = read_stored(username).map(|p| p.meta()).unwrap_or_default();
+    let now = now_secs();
+    meta.last_used = Some(now);
+    meta.last_verified = Some(now);
+    meta.last_status = Some("valid".to_string());
+
+    let profile = UserProfile {
+        username: username.to_string(),
+        cookies,
+        meta,
+        session_id: None,
+    };
+    if !profile.has_session() { return Err(anyhow!("No sessionid cookie to save for {}", username)); }
+    write_profile(&profile, config::get().profiles.encrypt)
+}
+
+pub fn list_profiles() -> Result<Vec<String>> {
+    let mut profiles = Vec::new();
+    ensure_profiles_dir()?;
+
+    let paths = fs::read_dir(&config::get().paths.profiles_dir)?;
+    for path in paths {
+        let p = path?.path();
+        if let Some(ext) = p.extension() {
+            if ext == "json" {
+                if let Some(stem) = p.file_stem() {
+                    profiles.push(stem.to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+    profiles.sort();
+    Ok(profiles)
+}
+
+pub fn profile_summaries() -> Result<Vec<Result<ProfileSummary>>> {
+    Ok(list_profiles()?
+        .into_iter()
+        .map(|name| {
+            let stored = read_stored(&name).map_err(|e| anyhow!("{}: {}", name, e))?;
+            Ok(ProfileSummary {
+                encrypted: matches!(stored, StoredProfile::Encrypted(_)),
+                meta: stored.meta(),
+                name,
+            })
+        })
+        .collect())
+}
+
+fn read_stored(username: &str) -> Result<StoredProfile> {
+    read_stored_file(&profile_path(username)?)
+}
+
+// Plain profiles are upgraded on read, so writing one back never drops a legacy session_id.
+fn read_stored_file(path: &Path) -> Result<StoredProfile> {
+    let data = fs::read_to_string(path)?;
+    Ok(match serde_json::from_str(&data)? {
+        StoredProfile::Plain(profile) => StoredProfile::Plain(profile.upgrade()),
+        encrypted => encrypted,
+    })
+}
+
+pub fn is_encrypted(username: &str) -> Result<bool> {
+    Ok(matches!(read_stored(username)?, StoredProfile::Encrypted(_)))
+}
+
+pub fn read_profile(username: &str) -> Result<UserProfile> {
+    let mut profile = match read_stored(username)? {
+        StoredProfile::Encrypted(envelope) => {
+            let mut profile = unlock(&envelope)?.0.upgrade();
+            profile.meta = envelope.meta;
+            profile
+        },
+        StoredProfile::Plain(profile) => {
+            if config::get().profiles.encrypt {
+                write_profile(&profile, true)?;
+                info!("Profile {} migrated to encrypted storage.", username);
+            }
+            profile
+        }
+    };
+    profile.username = username.to_string();
+    Ok(profile)
+}
+
+pub fn load_profile(username: &str) -> Result<UserProfile> {
+    let profile = read_profile(username)?;
+    if !profile.has_session() { return Err(anyhow!("Profile {} has no valid sessionid cookie", username)); }
+    Ok(profile)
+}
+
+pub fn record_verification(name: &str, status: &str) -> Result<()> {
+    write_verification(&ensure_exists(name)?, status)
+}
+
+fn write_verification(path: &Path, status: &str) -> Result<()> {
+    let mut stored = read_stored_file(path)?;
+    let meta = match &mut stored {
+        StoredProfile::Encrypted(envelope) => &mut envelope.meta,
+        StoredProfile::Plain(profile) => &mut profile.meta,
+    };
+    meta.last_verified = Some(now_secs());
+    meta.last_status = Some(status.to_string());
+
+    let json = match &stored {
+        StoredProfile::Encrypted(envelope) => serde_json::to_string_pretty(envelope)?,
+        StoredProfile::Plain(profile) => serde_json::to_string_pretty(profile)?,
+    };
+    write_private(path, &json)
+}
+
+pub fn encrypt_profile(username: &str) -> Result<bool> {
+    if is_encrypted(username)? { return Ok(false); }
+    let profile = load_profile(username)?;
+    write_profile(&profile, true)?;
+    Ok(true)
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(anyhow!("Invalid profile name '{}'", name));
+    }
+    Ok(())
+}
+
+fn ensure_exists(name: &str) -> Result<PathBuf> {
+    let path = profile_path(name)?;
+    if !path.is_file() { return Err(anyhow!("Profile {} does not exist", name)); }
+    Ok(path)
+}
+
+pub fn delete_profile(name: &str) -> Result<()> {
+    fs::remove_file(ensure_exists(name)?)?;
+    Ok(())
+}
+
+pub fn rename_profile(old: &str, new: &str) -> Result<()> {
+    let from = ensure_exists(old)?;
+    let to = profile_path(new)?;
+    if to.exists() { return Err(anyhow!("Profile {} already exists", new)); }
+    fs::rename(from, to)?;
+    Ok(())
+}
+
+pub fn export_profile(name: &str, dest: &Path, export_passphrase: &str) -> Result<()> {
+    let profile = load_profile(name)?;
+    let json = serde_json::to_string_pretty(&encrypt(&profile, export_passphrase)?)?;
+    write_private(dest, &json)
+}
+
+pub fn import_profile(src: &Path, export_passphrase: &str, name: Option<&str>, overwrite: bool) -> Result<String> {
+    let data = fs::read_to_string(src).map_err(|e| anyhow!("Failed to read {:?}: {}", src, e))?;
+    let envelope: EncryptedProfile = serde_json::from_str(&data).map_err(|e| anyhow!("{:?} is not an exported profile: {}", src, e))?;
+    let mut profile = decrypt(&envelope, export_passphrase)?.upgrade();
+
+    if let Some(name) = name { profile.username = name.to_string(); }
+    let path = profile_path(&profile.username)?;
+    if !profile.has_session() { return Err(anyhow!("Exported profile has no valid sessionid cookie")); }
+    if path.exists() && !overwrite {
+        return Err(anyhow!("Profile {} already exists (use --force to overwrite)", profile.username));
+    }
+
+    write_profile(&profile, config::get().profiles.encrypt)?;
+    Ok(profile.username)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_a_verification_keeps_a_legacy_session() {
+        let dir = std::env::temp_dir().join(format!("stov-profile-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("legacy.json");
+        fs::write(&path, r#"{"username": "legacy", "session_id": "abc123"}"#).unwrap();
+
+        let result = write_verification(&path, "valid").and_then(|_| read_stored_file(&path));
+        let data = fs::read_to_string(&path);
+        let _ = fs::remove_dir_all(&dir);
+
+        let Ok(StoredProfile::Plain(profile)) = result else { panic!("legacy profile was not read back as plain") };
+        assert!(profile.has_session());
+        assert_eq!(profile.cookies.iter().find(|c| c.name == "sessionid").map(|c| c.value.as_str()), Some("abc123"));
+        assert_eq!(profile.meta.last_status.as_deref(), Some("valid"));
+        assert!(data.unwrap().contains("\"sessionid\""));
+    }
+}
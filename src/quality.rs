@@ -0,0 +1,155 @@
+use base64::{Engine as _, engine::general_purpose};
+use std::fmt;
+
+// Short edge in pixels and bitrate in bits per second; unknown values rank lowest.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Quality {
+    pub size: Option<u32>,
+    pub bitrate: Option<u64>,
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.size, self.bitrate) {
+            (Some(size), Some(bitrate)) => write!(f, "{}p {} kbps", size, bitrate / 1000),
+            (Some(size), None) => write!(f, "{}p", size),
+            (None, Some(bitrate)) => write!(f, "{} kbps", bitrate / 1000),
+            (None, None) => write!(f, "unknown quality"),
+        }
+    }
+}
+
+fn query_param<'a>(url: &'a str, key: &str) -> Option<&'a str> {
+    let query = url.split_once('?')?.1;
+    query.split('&').find_map(|kv| kv.strip_prefix(key)?.strip_prefix('='))
+}
+
+pub fn decode_param(value: &str) -> Option<Vec<u8>> {
+    let encoded = value.replace("%3D", "=").replace("%2B", "+").replace("%2F", "/");
+    general_purpose::STANDARD.decode(&encoded)
+        .or_else(|_| general_purpose::STANDARD_NO_PAD.decode(encoded.trim_end_matches('=')))
+        .ok()
+}
+
+pub fn efg(url: &str) -> Option<String> {
+    let decoded = decode_param(query_param(url, "efg")?)?;
+    Some(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+// "1080x1920" -> 1080
+fn dimensions(token: &str) -> Option<u32> {
+    let (w, h) = token.split_once('x')?;
+    let w: u32 = w.parse().ok()?;
+    let h: u32 = h.parse().ok()?;
+    if w == 0 || h == 0 { return None; }
+    Some(w.min(h))
+}
+
+// The source asset shared by every representation of one story video (video, audio and other bitrates).
+pub fn asset_id(url: &str) -> Option<String> {
+    let json: serde_json::Value = serde_json::from_str(&efg(url)?).ok()?;
+    match &json["xpv_asset_id"] {
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::String(s) if !s.is_empty() => Some(s.clone()),
+        _ => None,
+    }
+}
+
+// CDN resize tokens such as "s640x640" or "p1080x1080", in the path or the stp= parameter.
+fn cdn_size(url: &str) -> Option<u32> {
+    let path = url.split('?').next().unwrap_or(url);
+    let stp = query_param(url, "stp").unwrap_or("");
+    path.split('/')
+        .chain(stp.split(['_', '-']))
+        .flat_map(|segment| segment.split('_'))
+        .filter_map(|token| token.strip_prefix('s').or_else(|| token.strip_prefix('p')))
+        .filter_map(dimensions)
+        .min()
+}
+
+// Encoding hints: "image_url_gen.1080x1920.sdr...", "...dash_h264-basic-gen2_720p" and a "bitrate" field.
+fn efg_quality(url: &str) -> Quality {
+    let Some(efg) = efg(url) else { return Quality::default() };
+    let json: serde_json::Value = serde_json::from_str(&efg).unwrap_or_default();
+    let tag = json["vencode_tag"].as_str().unwrap_or("");
+    let size = tag
+        .split(['.', '_', '-'])
+        .filter_map(|token| dimensions(token).or_else(|| token.strip_suffix('p')?.parse().ok()))
+        .filter(|size| *size >= 100)
+        .max();
+    Quality { size, bitrate: json["bitrate"].as_u64().filter(|b| *b > 0) }
+}
+
+pub fn estimate(url: &str, hint: Option<u32>) -> Quality {
+    let encoded = efg_quality(url);
+    Quality {
+        size: [hint, cdn_size(url), encoded.size].into_iter().flatten().max(),
+        bitrate: encoded.bitrate,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_efg(base: &str, json: &str) -> String {
+        format!("{}?efg={}&_nc_ht=cdn", base, general_purpose::STANDARD.encode(json).replace('=', "%3D"))
+    }
+
+    #[test]
+    fn reads_cdn_resize_tokens() {
+        let cases = [
+            ("https://cdn/v/t51/s640x640/123_n.jpg", Some(640)),
+            ("https://cdn/v/t51/p1080x1080/123_n.jpg", Some(1080)),
+            ("https://cdn/v/t51/123_n.jpg?stp=dst-jpg_e35_p750x1334_sh0.08", Some(750)),
+            ("https://cdn/v/t51/s150x150/123_n.jpg?stp=dst-jpg_p1080x1080", Some(150)),
+            ("https://cdn/v/t51/s0x0/123_n.jpg", None),
+            ("https://cdn/v/t51/123_n.jpg?stp=dst-jpg_e35", None),
+        ];
+        for (url, size) in cases {
+            assert_eq!(cdn_size(url), size, "{}", url);
+        }
+    }
+
+    #[test]
+    fn reads_efg_hints() {
+        let cases = [
+            (r#"{"vencode_tag":"image_url_gen.1080x1920.sdr.f75761.default_image"}"#, Quality { size: Some(1080), bitrate: None }),
+            (r#"{"vencode_tag":"xpv_progressive.INSTAGRAM.STORY.dash_h264-basic-gen2_720p","bitrate":1536000}"#, Quality { size: Some(720), bitrate: Some(1536000) }),
+            (r#"{"vencode_tag":"dash_ln_heaac_vbr3_audio","bitrate":0}"#, Quality::default()),
+            ("not json", Quality::default()),
+        ];
+        for (json, quality) in cases {
+            assert!(efg_quality(&with_efg("https://cdn/o1/v/t16/f2/m69/abc.mp4", json)) == quality, "{}", json);
+        }
+        assert!(efg_quality("https://cdn/o1/v/t16/f2/m69/abc.mp4") == Quality::default());
+    }
+
+    #[test]
+    fn reads_asset_ids() {
+        let cases = [
+            (r#"{"xpv_asset_id":17841400123}"#, Some("17841400123")),
+            (r#"{"xpv_asset_id":"17841400123"}"#, Some("17841400123")),
+            (r#"{"xpv_asset_id":""}"#, None),
+            (r#"{"vencode_tag":"dash_audio"}"#, None),
+        ];
+        for (json, id) in cases {
+            assert_eq!(asset_id(&with_efg("https://cdn/abc.mp4", json)).as_deref(), id, "{}", json);
+        }
+        assert_eq!(asset_id("https://cdn/abc.mp4"), None);
+    }
+
+    #[test]
+    fn estimate_takes_the_best_size_hint() {
+        let efg = with_efg("https://cdn/v/t51/s640x640/123_n.mp4", r#"{"vencode_tag":"dash_720p","bitrate":900000}"#);
+        let cases = [
+            (efg.as_str(), None, Quality { size: Some(720), bitrate: Some(900000) }),
+            (efg.as_str(), Some(1080), Quality { size: Some(1080), bitrate: Some(900000) }),
+            ("https://cdn/v/t51/s640x640/123_n.jpg", Some(320), Quality { size: Some(640), bitrate: None }),
+            ("https://cdn/v/t51/123_n.jpg", None, Quality::default()),
+        ];
+        for (url, hint, quality) in cases {
+            assert!(estimate(url, hint) == quality, "{} {:?}", url, hint);
+        }
+    }
+}
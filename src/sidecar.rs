@@ -0,0 +1,44 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use crate::overlay::Overlays;
+use crate::utils::write_atomic;
+
+#[derive(Serialize)]
+pub struct Sidecar<'a> {
+    pub target: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taken_at: Option<i64>,
+    pub captured_at: i64,
+    pub source: &'a str,
+    pub url: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_url: Option<&'a str>,
+    pub position: usize,
+    pub mime: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    pub size: u64,
+    pub sha256: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt: Option<&'a str>,
+    #[serde(skip_serializing_if = "Overlays::is_empty")]
+    pub overlays: &'a Overlays,
+}
+
+pub fn path_for(media: &Path) -> PathBuf {
+    let mut name = media.as_os_str().to_os_string();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+pub fn write(media: &Path, sidecar: &Sidecar) -> Result<()> {
+    let json = serde_json::to_string_pretty(sidecar)?;
+    write_atomic(&path_for(media), json.as_bytes())
+}
@@ -0,0 +1,127 @@
+use anyhow::{Result, anyhow};
+use std::fs;
+use std::path::{Component, Path};
+use crate::template::Template;
+
+#[derive(Clone, Copy)]
+pub struct MediaFilter {
+    pub images: bool,
+    pub videos: bool,
+}
+
+impl Default for MediaFilter {
+    fn default() -> Self {
+        Self { images: true, videos: true }
+    }
+}
+
+impl MediaFilter {
+    pub fn allows(&self, ext: &str) -> bool {
+        if ext == "mp4" { self.videos } else { self.images }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        let mut filter = Self { images: false, videos: false };
+        for kind in value.split(',').map(|s| s.trim().to_lowercase()) {
+            match kind.as_str() {
+                "image" | "images" | "photo" | "photos" => filter.images = true,
+                "video" | "videos" => filter.videos = true,
+                "all" => return Ok(Self::default()),
+                _ => return Err(anyhow!("unknown media type '{}' (expected image, video or all)", kind)),
+            }
+        }
+        Ok(filter)
+    }
+}
+
+#[derive(Clone)]
+pub struct Target {
+    pub username: String,
+    pub media: MediaFilter,
+    pub folder: Option<String>,
+    pub template: Option<Template>,
+    pub highlights: bool,
+    pub enabled: bool,
+}
+
+impl Target {
+    pub fn new(username: &str) -> Result<Self> {
+        validate_username(username)?;
+        Ok(Self {
+            username: username.to_string(),
+            media: MediaFilter::default(),
+            folder: None,
+            template: None,
+            highlights: false,
+            enabled: true,
+        })
+    }
+
+    fn set_option(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "media" => self.media = MediaFilter::parse(value)?,
+            "folder" => {
+                validate_folder(value)?;
+                self.folder = Some(value.to_string());
+            },
+            "template" => self.template = Some(Template::parse(value)?),
+            "highlights" => self.highlights = parse_bool(key, value)?,
+            "enabled" => self.enabled = parse_bool(key, value)?,
+            _ => return Err(anyhow!("unknown option '{}' (expected media, folder, template, highlights or enabled)", key)),
+        }
+        Ok(())
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool> {
+    match value {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(anyhow!("invalid value for '{}': '{}'", key, value)),
+    }
+}
+
+fn validate_username(username: &str) -> Result<()> {
+    if username.is_empty() || !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_') {
+        return Err(anyhow!("invalid username '{}'", username));
+    }
+    Ok(())
+}
+
+fn validate_folder(folder: &str) -> Result<()> {
+    let path = Path::new(folder);
+    if folder.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(anyhow!("folder '{}' must be a relative path inside the download directory", folder));
+    }
+    Ok(())
+}
+
+pub fn parse_targets(text: &str) -> Result<Vec<Target>> {
+    let mut targets = Vec::new();
+
+    for (idx, raw) in text.lines().enumerate() {
+        let line = raw.split('#').next().unwrap_or("").trim();
+        if line.is_empty() { continue; }
+
+        let parse_line = || -> Result<Target> {
+            let mut fields = line.split_whitespace();
+            let mut target = Target::new(fields.next().unwrap_or(""))?;
+            for field in fields {
+                let (key, value) = field
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("expected key=value, found '{}'", field))?;
+                target.set_option(key, value)?;
+            }
+            Ok(target)
+        };
+
+        let target = parse_line().map_err(|e| anyhow!("line {}: {}", idx + 1, e))?;
+        targets.push(target);
+    }
+    Ok(targets)
+}
+
+pub fn load_targets_file(path: &Path) -> Result<Vec<Target>> {
+    let data = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read targets file {:?}: {}", path, e))?;
+    parse_targets(&data).map_err(|e| anyhow!("Invalid targets file {:?}: {}", path, e))
+}
@@ -0,0 +1,107 @@
+use anyhow::{Result, anyhow};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{Local, TimeZone};
+use std::path::{Component, Path};
+
+pub const DEFAULT_TEMPLATE: &str = "{username}_{taken_at}.{ext}";
+
+#[derive(Clone)]
+enum Part {
+    Literal(String),
+    Username,
+    TakenAt,
+    Date(String),
+    MediaId,
+    Ext,
+    Source,
+}
+
+#[derive(Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+pub struct TemplateVars<'a> {
+    pub username: &'a str,
+    pub taken_at: i64,
+    pub media_id: &'a str,
+    pub ext: &'a str,
+    pub source: &'a str,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            if rest[..start].contains('}') { return Err(anyhow!("unmatched '}}' in template {:?}", text)); }
+            if start > 0 { parts.push(Part::Literal(rest[..start].to_string())); }
+            let end = rest[start..].find('}').ok_or_else(|| anyhow!("unclosed '{{' in template {:?}", text))?;
+            let name = &rest[start + 1..start + end];
+            parts.push(match name.split_once(':') {
+                Some(("date", fmt)) => {
+                    if fmt.is_empty() || StrftimeItems::new(fmt).any(|item| matches!(item, Item::Error)) {
+                        return Err(anyhow!("invalid date format {:?} in template", fmt));
+                    }
+                    Part::Date(fmt.to_string())
+                },
+                _ => match name {
+                    "username" => Part::Username,
+                    "taken_at" => Part::TakenAt,
+                    "date" => Part::Date("%Y-%m-%d".to_string()),
+                    "media_id" => Part::MediaId,
+                    "ext" => Part::Ext,
+                    "source" => Part::Source,
+                    _ => return Err(anyhow!("unknown template variable {{{}}} (expected username, taken_at, date, date:FORMAT, media_id, ext or source)", name)),
+                },
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if rest.contains('}') { return Err(anyhow!("unmatched '}}' in template {:?}", text)); }
+        if !rest.is_empty() { parts.push(Part::Literal(rest.to_string())); }
+
+        let template = Self { parts };
+        if !template.parts.iter().any(|p| matches!(p, Part::Ext)) {
+            return Err(anyhow!("template {:?} must contain {{ext}}", text));
+        }
+        let sample = template.render(&TemplateVars {
+            username: "user",
+            taken_at: 0,
+            media_id: "0",
+            ext: "jpg",
+            source: "net",
+        });
+        let path = Path::new(&sample);
+        if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(anyhow!("template {:?} must produce a relative path inside the download directory", text));
+        }
+        Ok(template)
+    }
+
+    pub fn render(&self, vars: &TemplateVars) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Username => out.push_str(&sanitize(vars.username)),
+                Part::TakenAt => out.push_str(&vars.taken_at.to_string()),
+                Part::Date(fmt) => {
+                    let date = Local.timestamp_opt(vars.taken_at, 0).single().unwrap_or_default();
+                    out.push_str(&sanitize(&date.format(fmt).to_string()));
+                },
+                Part::MediaId => out.push_str(&sanitize(vars.media_id)),
+                Part::Ext => out.push_str(&sanitize(vars.ext)),
+                Part::Source => out.push_str(&sanitize(&vars.source.to_lowercase())),
+            }
+        }
+        out
+    }
+}
+
+pub fn sanitize(value: &str) -> String {
+    let clean: String = value
+        .chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c })
+        .collect();
+    if clean.is_empty() || clean.chars().all(|c| c == '.') { "_".to_string() } else { clean }
+}
@@ -1,84 +1,113 @@
-use colored::*;
 use std::fs;
-use std::path::Path;
-use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::io::{ErrorKind, Write};
+use std::time::Duration;
 use anyhow::{Result, anyhow};
 use rand::Rng;
 use base64::{Engine as _, engine::general_purpose}; 
-use serde::{Serialize, Deserialize};
-use crate::config::{DOWNLOAD_DIR, IMAGES_DIR, PROOF_DIR, ERROR_DIR, PROFILES_DIR};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+use crate::config;
+use crate::download::TempMedia;
+use crate::media::MediaInfo;
+use crate::profile::ensure_profiles_dir;
 
 
-#[derive(Serialize, Deserialize)]
-pub struct UserProfile {
-    pub username: String,
-    pub session_id: String,
-}
-
 pub fn setup_env() {
-    let paths = vec![DOWNLOAD_DIR, IMAGES_DIR, PROOF_DIR, ERROR_DIR, PROFILES_DIR];
+    let dirs = &config::get().paths;
+    let paths = vec![&dirs.download_dir, &dirs.images_dir, &dirs.proof_dir, &dirs.error_dir];
     for p in paths {
         let path = Path::new(p);
         if !path.exists() { let _ = fs::create_dir_all(path); }
     }
+    let _ = ensure_profiles_dir();
+    remove_stale_temp_files(Path::new(&dirs.download_dir));
+    remove_stale_temp_files(Path::new(&dirs.profiles_dir));
 }
 
-pub fn log_info(msg: &str) {
-    println!("{} {}", "[INFO]".green().bold(), msg);
-}
+const TEMP_PREFIX: &str = ".stov-";
+const TEMP_SUFFIX: &str = ".part";
+const STALE_TEMP_AGE: Duration = Duration::from_secs(24 * 3600);
 
-pub fn log_error(msg: &str) {
-    eprintln!("{} {}", "[ERROR]".red().bold(), msg);
+pub fn temp_path(dir: &Path) -> PathBuf {
+    dir.join(format!("{}{}-{:08x}{}", TEMP_PREFIX, std::process::id(), rand::random::<u32>(), TEMP_SUFFIX))
 }
 
+fn owner_alive(name: &str, path: &Path) -> bool {
+    let Some(pid) = name.strip_prefix(TEMP_PREFIX).and_then(|rest| rest.split('-').next()) else { return false };
+    if pid == std::process::id().to_string() { return true; }
+    let proc = Path::new("/proc");
+    if proc.is_dir() { return proc.join(pid).exists(); }
+    // Without /proc the owner can't be looked up, so only files untouched for a day count as abandoned.
+    let age = fs::metadata(path).and_then(|m| m.modified()).ok().and_then(|t| t.elapsed().ok());
+    age.is_none_or(|age| age < STALE_TEMP_AGE)
+}
 
-pub fn clear_terminal() {
-    print!("\x1b[2J\x1b[3J\x1b[H");
-    let _ = std::io::stdout().flush();
+fn remove_stale_temp_files(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        if path.is_dir() {
+            remove_stale_temp_files(&path);
+        } else if name.starts_with(TEMP_PREFIX) && name.ends_with(TEMP_SUFFIX) && !owner_alive(&name, &path) {
+            match fs::remove_file(&path) {
+                Ok(()) => info!("Removed stale temporary file {:?}", path),
+                Err(e) => warn!("Failed to remove stale temporary file {:?}: {}", path, e),
+            }
+        }
+    }
 }
 
-pub fn save_profile(username: &str, session_id: &str) -> Result<()> {
-    let profile = UserProfile {
-        username: username.to_string(),
-        session_id: session_id.to_string(),
-    };
-    let json = serde_json::to_string_pretty(&profile)?;
-    let filename = format!("{}/{}.json", PROFILES_DIR, username);
-    let mut file = fs::File::create(filename)?;
-    file.write_all(json.as_bytes())?;
-    log_info(&format!("Session saved for user: {}", username));
+fn sync_dir(dir: &Path) -> Result<()> {
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
     Ok(())
 }
 
-pub fn list_profiles() -> Result<Vec<String>> {
-    let mut profiles = Vec::new();
+pub fn parent_dir(path: &Path) -> &Path {
+    path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."))
+}
 
-    if !Path::new(PROFILES_DIR).exists() {
-        fs::create_dir_all(PROFILES_DIR)?;
-    }
-    
-    let paths = fs::read_dir(PROFILES_DIR)?;
-    for path in paths {
-        let p = path?.path();
-        if let Some(ext) = p.extension() {
-            if ext == "json" {
-                if let Some(stem) = p.file_stem() {
-                    profiles.push(stem.to_string_lossy().to_string());
-                }
+pub fn move_into_place(src: &Path, dest: &Path) -> Result<()> {
+    let dir = parent_dir(dest);
+    match fs::rename(src, dest) {
+        Ok(()) => {},
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            let staged = temp_path(dir);
+            let copied = fs::copy(src, &staged)
+                .and_then(|_| fs::File::open(&staged)?.sync_all())
+                .and_then(|_| fs::rename(&staged, dest));
+            if let Err(e) = copied {
+                let _ = fs::remove_file(&staged);
+                return Err(e.into());
             }
-        }
+            fs::remove_file(src)?;
+        },
+        Err(e) => return Err(e.into()),
     }
-    Ok(profiles)
+    sync_dir(dir)
 }
 
-pub fn load_profile_session(username: &str) -> Result<String> {
-    let path = format!("{}/{}.json", PROFILES_DIR, username);
-    let data = fs::read_to_string(&path)?;
-    let profile: UserProfile = serde_json::from_str(&data)?;
-    Ok(profile.session_id)
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let temp = temp_path(parent_dir(path));
+    let written = fs::File::create(&temp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.map_err(anyhow::Error::from).and_then(|_| move_into_place(&temp, path)) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    Ok(())
 }
 
 
+pub fn clear_terminal() {
+    print!("\x1b[2J\x1b[3J\x1b[H");
+    let _ = std::io::stdout().flush();
+}
+
 pub fn save_screenshot(data: Vec<u8>, folder: &str, base_name: &str) -> Result<()> {
     if !Path::new(folder).exists() { fs::create_dir_all(folder)?; }
     let mut rng = rand::thread_rng();
@@ -86,7 +115,7 @@ pub fn save_screenshot(data: Vec<u8>, folder: &str, base_name: &str) -> Result<(
     let filename = format!("{}{}.png", base_name, unique_id);
     let path = Path::new(folder).join(filename);
     fs::write(&path, data)?;
-    log_info(&format!("Evidence saved: {:?}", path));
+    info!("Evidence saved: {:?}", path);
     Ok(())
 }
 
@@ -97,27 +126,68 @@ pub fn save_html(text: String, folder: &str, base_name: &str) {
     let _ = fs::write(&path, text);
 }
 
-pub fn save_base64_file(base64_string: &str, filename: &str) -> Result<()> {
-    let path = format!("{}/{}", DOWNLOAD_DIR, filename);
-    
-    let clean_string = if let Some(index) = base64_string.find(',') {
-        &base64_string[index + 1..]
-    } else {
-        base64_string
-    };
+pub struct SavedMedia {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+    pub mime: &'static str,
+    pub info: MediaInfo,
+}
 
-    let bytes = general_purpose::STANDARD.decode(clean_string)?;
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
 
-    
-    let min_size = if filename.ends_with(".mp4") { 200_000 } else { 15_000 };
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
 
-    if bytes.len() < min_size {
-        return Err(anyhow!("File too small ({} bytes). Rejected.", bytes.len()));
+pub fn decode_data_uri(data_uri: &str) -> Result<Vec<u8>> {
+    let clean_string = if let Some(index) = data_uri.find(',') {
+        &data_uri[index + 1..]
+    } else {
+        data_uri
+    };
+    Ok(general_purpose::STANDARD.decode(clean_string)?)
+}
+
+// Claims the first free name with create_new, so two processes sharing a directory never pick the same one.
+fn reserve_unique_path(path: &Path) -> Result<PathBuf> {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    for n in 0..10_000 {
+        let candidate = if n == 0 { path.to_path_buf() } else { path.with_file_name(format!("{}_{}{}", stem, n, ext)) };
+        match fs::OpenOptions::new().write(true).create_new(true).open(&candidate) {
+            Ok(_) => return Ok(candidate),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
     }
+    Err(anyhow!("No free file name for {:?}", path))
+}
 
-    let mut file = fs::File::create(&path)?;
-    file.write_all(&bytes)?;
-    
-    log_info(&format!("Media Saved via Browser Fetch (Size: {} KB): {}", bytes.len() / 1024, filename));
-    Ok(())
+pub fn persist_media(temp: TempMedia, info: MediaInfo, filename: &str) -> Result<SavedMedia> {
+    let wanted = Path::new(&config::get().paths.download_dir).join(filename);
+    if let Some(parent) = wanted.parent() { fs::create_dir_all(parent)?; }
+    let path = reserve_unique_path(&wanted)?;
+    // Renaming over the empty placeholder only ever replaces the name reserved above.
+    if let Err(e) = move_into_place(&temp.path, &path) {
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+    let path = path.to_string_lossy().into_owned();
+
+    info!("Media Saved (Size: {} KB): {}", temp.size / 1024, path);
+    let saved = SavedMedia {
+        path,
+        size: temp.size,
+        sha256: temp.sha256.clone(),
+        mime: info.kind.mime(),
+        info,
+    };
+    temp.persisted();
+    Ok(saved)
 }